@@ -0,0 +1,505 @@
+//! A stand-in for the Sharpener server, serving the exercises of a fixtures
+//! directory. Every exercise starts out as a pending submission, and forfeits and
+//! submissions update that state for as long as the server runs.
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "sharpener-mock-server",
+    about = "Serve fixture exercises through the Sharpener API"
+)]
+struct Args {
+    #[structopt(
+        parse(from_os_str),
+        help = "Directory with one exercise per sub-directory, each with a .meta.json"
+    )]
+    fixtures: PathBuf,
+    #[structopt(
+        long,
+        default_value = "5000",
+        help = "Port to listen on, 0 picks a free one"
+    )]
+    port: u16,
+    #[structopt(
+        long,
+        default_value = "20",
+        help = "Submissions per page when listing them"
+    )]
+    page_size: usize,
+}
+
+struct Exercise {
+    name: String,
+    language: String,
+    topics: Vec<String>,
+    archive: Vec<u8>,
+    checksum: String,
+}
+
+#[derive(Clone, Serialize)]
+struct Submission {
+    exercise_name: String,
+    exercise_language: String,
+    download_url: String,
+    submission_token: String,
+    attempts: i32,
+    submission_status: &'static str,
+    exercise_topics: Vec<String>,
+    archive_checksum: String,
+    archive_size: usize,
+}
+
+/// The grading of a solution, moving on a state every time it is looked at.
+struct Grading {
+    id: String,
+    state: usize,
+    passed: bool,
+}
+
+const GRADING_STATES: [&str; 3] = ["queued", "grading", "graded"];
+
+impl Grading {
+    fn to_json(&self) -> serde_json::Value {
+        let state = GRADING_STATES[self.state];
+        if state != "graded" {
+            return json!({ "submission_id": self.id, "state": state });
+        }
+        let (verdict, score, comment) = if self.passed {
+            ("passed", 10, "Every test passes, well done!")
+        } else {
+            ("failed", 0, "Some tests are still failing.")
+        };
+        json!({
+            "submission_id": self.id,
+            "state": state,
+            "verdict": verdict,
+            "score": score,
+            "max_score": 10,
+            "comments": [{ "reviewer": "Sharpener", "message": comment }],
+        })
+    }
+
+    fn advance(&mut self) {
+        self.state = (self.state + 1).min(GRADING_STATES.len() - 1);
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    authorized: bool,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = component.get(index + 1..index + 3);
+        match (
+            bytes[index],
+            hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+        ) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Value of the `name` field of a multipart form body.
+fn form_field(body: &[u8], name: &str) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let field = &body[body.find(&format!("name=\"{}\"", name))?..];
+    let value = &field[field.find("\r\n\r\n")? + 4..];
+    Some(value[..value.find("\r\n")?].to_owned())
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = decode(parts.next().unwrap());
+            (key, decode(parts.next().unwrap_or_default()))
+        })
+        .collect()
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn error(status: u16, code: &str, message: &str) -> Self {
+        Self::json(
+            status,
+            json!({ "success": false, "code": code, "message": message }),
+        )
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        409 => "Conflict",
+        _ => "Internal Server Error",
+    }
+}
+
+fn not_found() -> Response {
+    Response::error(404, "not_found", "Submission not found")
+}
+
+fn already_graded() -> Response {
+    Response::error(409, "already_graded", "The submission is not pending")
+}
+
+fn load_exercise(dir: &Path) -> io::Result<Exercise> {
+    let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+    let meta: serde_json::Value = serde_json::from_reader(File::open(dir.join(".meta.json"))?)?;
+    let language = meta["language"].as_str().unwrap_or("rust").to_owned();
+    let topics = meta["topics"]
+        .as_array()
+        .map(|topics| {
+            topics
+                .iter()
+                .filter_map(|topic| topic.as_str().map(str::to_owned))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    builder.append_dir_all(&name, dir)?;
+    let archive = builder.into_inner()?.finish()?;
+    let checksum = format!("sha256:{:x}", Sha256::digest(&archive));
+    Ok(Exercise {
+        name,
+        language,
+        topics,
+        archive,
+        checksum,
+    })
+}
+
+fn load_exercises(fixtures: &Path) -> io::Result<Vec<Exercise>> {
+    let mut dirs: Vec<_> = fs::read_dir(fixtures)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    dirs.retain(|dir| dir.join(".meta.json").is_file());
+    dirs.sort();
+    dirs.iter().map(|dir| load_exercise(dir)).collect()
+}
+
+struct Server {
+    base_url: String,
+    page_size: usize,
+    exercises: Vec<Exercise>,
+    submissions: Vec<Submission>,
+    gradings: HashMap<String, Grading>,
+}
+
+impl Server {
+    fn new(base_url: String, page_size: usize, exercises: Vec<Exercise>) -> Self {
+        let mut server = Self {
+            base_url,
+            page_size: page_size.max(1),
+            exercises,
+            submissions: Vec::new(),
+            gradings: HashMap::new(),
+        };
+        for index in 0..server.exercises.len() {
+            server.assign(index);
+        }
+        server
+    }
+
+    /// Creates a pending submission for the exercise at `index`.
+    fn assign(&mut self, index: usize) -> Submission {
+        let exercise = &self.exercises[index];
+        let submission = Submission {
+            exercise_name: exercise.name.clone(),
+            exercise_language: exercise.language.clone(),
+            download_url: format!("{}archives/{}.tar.gz", self.base_url, exercise.name),
+            submission_token: format!("token-{}", self.submissions.len() + 1),
+            attempts: 0,
+            submission_status: "pending",
+            exercise_topics: exercise.topics.clone(),
+            archive_checksum: exercise.checksum.clone(),
+            archive_size: exercise.archive.len(),
+        };
+        self.submissions.push(submission.clone());
+        submission
+    }
+
+    fn submission(&mut self, token: &str) -> Option<&mut Submission> {
+        self.submissions
+            .iter_mut()
+            .find(|submission| submission.submission_token == token)
+    }
+
+    /// Lists the submissions matching the filters of `request`, a page at a time.
+    /// The cursor is the position of the first submission of the page.
+    fn list(&self, request: &Request) -> Response {
+        let matching: Vec<&Submission> = self
+            .submissions
+            .iter()
+            .filter(|submission| {
+                let status = request.param("status");
+                let language = request.param("language");
+                let topic = request.param("topic");
+                status
+                    .iter()
+                    .all(|&status| submission.submission_status == status)
+                    && language
+                        .iter()
+                        .all(|&language| submission.exercise_language == language)
+                    && topic
+                        .iter()
+                        .all(|&topic| submission.exercise_topics.iter().any(|t| t == topic))
+            })
+            .collect();
+
+        let start = match request.param("cursor").map(str::parse::<usize>) {
+            None => 0,
+            Some(Ok(start)) if start <= matching.len() => start,
+            Some(_) => return Response::error(400, "invalid_cursor", "The cursor is invalid"),
+        };
+        let end = (start + self.page_size).min(matching.len());
+        let next_cursor = Some(end.to_string()).filter(|_| end < matching.len());
+        Response::json(
+            200,
+            json!({ "data": &matching[start..end], "next_cursor": next_cursor }),
+        )
+    }
+
+    /// Skips the submission and hands out the next exercise in the same language.
+    fn forfeit(&mut self, token: &str) -> Response {
+        let submission = match self.submission(token) {
+            Some(submission) if submission.submission_status == "pending" => submission,
+            Some(_) => return already_graded(),
+            None => return not_found(),
+        };
+        submission.submission_status = "skipped";
+        let (name, language) = (
+            submission.exercise_name.clone(),
+            submission.exercise_language.clone(),
+        );
+
+        let current = self.exercises.iter().position(|e| e.name == name).unwrap();
+        let next = (1..=self.exercises.len())
+            .map(|offset| (current + offset) % self.exercises.len())
+            .find(|&index| self.exercises[index].language == language)
+            .unwrap();
+        let replacement = self.assign(next);
+        Response::json(200, json!({ "success": true, "data": replacement }))
+    }
+
+    /// Accepts a solution, which passes when its tests did.
+    fn submit(&mut self, token: &str, body: &[u8]) -> Response {
+        match self.submission(token) {
+            Some(submission) if submission.submission_status != "pending" => already_graded(),
+            Some(submission) => {
+                submission.attempts += 1;
+                submission.submission_status = "submitted";
+                eprintln!("Received {} bytes for {}", body.len(), token);
+                let grading = Grading {
+                    id: format!("solution-{}", token.trim_start_matches("token-")),
+                    state: 0,
+                    passed: form_field(body, "test_exit_code").as_deref() == Some("0"),
+                };
+                let response = json!({ "success": true, "data": grading.to_json() });
+                self.gradings.insert(token.to_owned(), grading);
+                Response::json(200, response)
+            }
+            None => not_found(),
+        }
+    }
+
+    fn grading(&mut self, token: &str) -> Response {
+        match self.gradings.get_mut(token) {
+            Some(grading) => {
+                grading.advance();
+                Response::json(200, grading.to_json())
+            }
+            None => Response::error(404, "not_found", "No solution was submitted yet"),
+        }
+    }
+
+    fn handle(&mut self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        if segments.first() == Some(&"api") && !request.authorized {
+            return Response::error(401, "token_invalid", "No token was sent");
+        }
+
+        match (request.method.as_str(), &segments[..]) {
+            ("GET", ["api", "submissions"]) => self.list(request),
+            ("GET", ["api", "submissions", token]) => match self.submission(token) {
+                Some(submission) => Response::json(200, json!(submission)),
+                None => not_found(),
+            },
+            ("GET", ["api", "submissions", token, "grading"]) => self.grading(token),
+            ("POST", ["api", "submissions", token, "forfeit"]) => self.forfeit(token),
+            ("POST", ["api", "submissions", token]) => self.submit(token, &request.body),
+            ("GET", ["archives", file]) => {
+                let name = file.trim_end_matches(".tar.gz");
+                match self.exercises.iter().find(|exercise| exercise.name == name) {
+                    Some(exercise) => Response {
+                        status: 200,
+                        content_type: "application/gzip",
+                        body: exercise.archive.clone(),
+                    },
+                    None => Response::error(404, "not_found", "Archive not found"),
+                }
+            }
+            _ => Response::error(404, "not_found", "Not found"),
+        }
+    }
+}
+
+fn read_chunked(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let size = usize::from_str_radix(line.trim().split(';').next().unwrap(), 16)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let mut chunk = vec![0; size + 2];
+        reader.read_exact(&mut chunk)?;
+        if size == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(&chunk[..size]);
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let target = parts.next().unwrap_or_default();
+    let mut target = target.splitn(2, '?');
+    let path = target.next().unwrap().to_owned();
+    let query = parse_query(target.next().unwrap_or_default());
+
+    let (mut authorized, mut length, mut chunked) = (false, 0, false);
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(index) = line.find(':') {
+            let value = line[index + 1..].trim();
+            match line[..index].to_lowercase().as_str() {
+                "authorization" => authorized = !value.is_empty(),
+                "content-length" => length = value.parse().unwrap_or(0),
+                "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+                _ => (),
+            }
+        }
+    }
+
+    let body = if chunked {
+        read_chunked(&mut reader)?
+    } else {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        body
+    };
+    Ok(Request {
+        method,
+        path,
+        query,
+        authorized,
+        body,
+    })
+}
+
+fn respond(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::from_args();
+    let listener = TcpListener::bind(("127.0.0.1", args.port))?;
+    let base_url = format!("http://{}/", listener.local_addr()?);
+    let mut server = Server::new(
+        base_url.clone(),
+        args.page_size,
+        load_exercises(&args.fixtures)?,
+    );
+
+    println!("Listening on {}api/", base_url);
+    io::stdout().flush()?;
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let response = match read_request(&stream) {
+            Ok(request) => {
+                let response = server.handle(&request);
+                eprintln!("{} {} -> {}", request.method, request.path, response.status);
+                response
+            }
+            Err(error) => Response::error(400, "bad_request", &error.to_string()),
+        };
+        if let Err(error) = respond(&stream, &response) {
+            eprintln!("Could not respond: {}", error);
+        }
+    }
+    Ok(())
+}
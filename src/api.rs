@@ -0,0 +1,541 @@
+use crate::checksum::Algorithm;
+use crate::config::Config;
+use crate::download::{self, Fetched};
+use crate::error::*;
+use crate::grading::Grading;
+use crate::http::{self, Http};
+use crate::manifest::Manifest;
+use crate::submission::{Submission, SubmissionQuery};
+use reqwest::{multipart::Form, Method, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Results of a graded test run, sent to the server along with the solution.
+pub struct SubmissionReport {
+    pub test_output: String,
+    pub test_errors: String,
+    pub test_log: String,
+    pub test_exit_code: Option<i32>,
+    pub test_coverage: String,
+    /// Hex digest of `test_checksums` made with `test_checksum_algorithm`, see
+    /// `manifest::digest` for what it covers.
+    pub test_checksum: String,
+    pub test_checksum_algorithm: Algorithm,
+    pub test_checksums: Manifest,
+    pub solution_file: PathBuf,
+}
+
+/// The Sharpener API, as used by the commands.
+pub trait ApiClient: Send + Sync {
+    /// Lists the submissions of the user matching `query`, across every page.
+    fn list(&self, query: &SubmissionQuery) -> Result<Vec<Submission>>;
+
+    fn get(&self, token: &str) -> Result<Submission>;
+
+    /// Gives up on a submission and returns the replacement exercise.
+    fn forfeit(&self, token: &str) -> Result<Submission>;
+
+    /// Sends a solution, returning its grading as it starts when the server
+    /// grades solutions.
+    fn submit(&self, token: &str, report: SubmissionReport) -> Result<Option<Grading>>;
+
+    /// The latest grading of the last solution sent for a submission.
+    fn grading(&self, token: &str) -> Result<Grading>;
+
+    /// Downloads an exercise archive.
+    fn download(&self, url: &str, progress: bool) -> Result<Fetched>;
+}
+
+/// A page of submissions. Servers without pagination send a plain list instead.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SubmissionPage {
+    Page {
+        data: Vec<Submission>,
+        next_cursor: Option<String>,
+    },
+    All(Vec<Submission>),
+}
+
+#[derive(Deserialize)]
+struct ForfeitSubmission {
+    pub success: bool,
+    pub data: Option<Submission>,
+}
+
+#[derive(Deserialize)]
+struct SubmittedSolution {
+    #[serde(default)]
+    pub data: Option<Grading>,
+}
+
+/// Details the server sends along with unsuccessful responses.
+#[derive(Default, Deserialize)]
+struct ErrorBody {
+    message: Option<String>,
+    code: Option<String>,
+
+    /// Validation errors, by field.
+    #[serde(default)]
+    errors: BTreeMap<String, Vec<String>>,
+}
+
+/// Turns an unsuccessful response into the error it describes. The `code` in the
+/// body is preferred over the status code to tell errors apart.
+fn server_error(status: StatusCode, retry_after: Option<Duration>, body: &[u8]) -> Error {
+    let body: ErrorBody = serde_json::from_slice(body).unwrap_or_default();
+    let code = body.code.as_deref().unwrap_or(match status {
+        StatusCode::UNAUTHORIZED => "token_expired",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::CONFLICT => "already_graded",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        _ => "",
+    });
+    let message = match (body.message, code) {
+        (Some(message), _) => message,
+        (None, "") => {
+            return Error::InvalidAPIResponse {
+                expected: StatusCode::OK,
+                received: status,
+            }
+        }
+        (None, _) => status
+            .canonical_reason()
+            .unwrap_or("Request failed")
+            .to_owned(),
+    };
+
+    match code {
+        "token_expired" | "token_invalid" => Error::ExpiredToken { message },
+        "not_found" => Error::ExerciseNotFound { message },
+        "already_graded" => Error::SubmissionGraded { message },
+        "rate_limited" => Error::RateLimited {
+            retry_after: retry_after.map(|delay| delay.as_secs()),
+        },
+        _ => Error::ServerRejected {
+            status,
+            message,
+            field_errors: body
+                .errors
+                .iter()
+                .map(|(field, errors)| format!("\n  {}: {}", field, errors.join(", ")))
+                .collect(),
+        },
+    }
+}
+
+/// Talks to the Sharpener server over HTTP.
+pub struct HttpClient {
+    http: Http,
+}
+
+impl HttpClient {
+    pub fn new(config: &Config, api: Url) -> Result<Self> {
+        Ok(Self {
+            http: Http::new(config, api)?,
+        })
+    }
+
+    fn expect_ok(mut response: reqwest::Response) -> Result<reqwest::Response> {
+        if response.status() == StatusCode::OK {
+            return Ok(response);
+        }
+        let retry_after = http::retry_after(&response);
+        let mut body = Vec::new();
+        // The status alone still makes a useful error when the body is unreadable.
+        let _ = response.read_to_end(&mut body);
+        Err(server_error(response.status(), retry_after, &body))
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str, params: &[(&str, String)]) -> Result<T> {
+        let response = self
+            .http
+            .send_idempotent(|| self.http.api_request(Method::GET, path).query(params))
+            .context(ServerRequest {})?;
+        serde_json::from_reader(Self::expect_ok(response)?).context(ParseSubmissionResponse {})
+    }
+}
+
+impl ApiClient for HttpClient {
+    fn list(&self, query: &SubmissionQuery) -> Result<Vec<Submission>> {
+        let mut submissions = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut params = query.params();
+            params.extend(cursor.iter().map(|cursor| ("cursor", cursor.clone())));
+            match self.get_json("submissions", &params)? {
+                SubmissionPage::All(page) => {
+                    submissions.extend(page);
+                    return Ok(submissions);
+                }
+                SubmissionPage::Page { data, next_cursor } => {
+                    submissions.extend(data);
+                    // A cursor that does not move on would never end the listing.
+                    if next_cursor.is_none() || next_cursor == cursor {
+                        return Ok(submissions);
+                    }
+                    cursor = next_cursor;
+                }
+            }
+        }
+    }
+
+    fn get(&self, token: &str) -> Result<Submission> {
+        self.get_json(&format!("submissions/{}", token), &[])
+    }
+
+    fn forfeit(&self, token: &str) -> Result<Submission> {
+        let response = self
+            .http
+            .api_request(Method::POST, &format!("submissions/{}/forfeit", token))
+            .send()
+            .context(ServerRequest {})?;
+        let forfeit: ForfeitSubmission = serde_json::from_reader(Self::expect_ok(response)?)
+            .context(ParseSubmissionResponse {})?;
+        match (forfeit.success, forfeit.data) {
+            (true, Some(submission)) => Ok(submission),
+            _ => Err(Error::InvalidForfeit),
+        }
+    }
+
+    fn submit(&self, token: &str, report: SubmissionReport) -> Result<Option<Grading>> {
+        let form = Form::new()
+            .text("test_output", report.test_output)
+            .text("test_errors", report.test_errors)
+            .text("test_log", report.test_log)
+            .text(
+                "test_exit_code",
+                report
+                    .test_exit_code
+                    .map_or_else(String::new, |code| code.to_string()),
+            )
+            .text("test_coverage", report.test_coverage)
+            .text("test_checksum", report.test_checksum)
+            .text(
+                "test_checksum_algorithm",
+                report.test_checksum_algorithm.as_str(),
+            )
+            .text(
+                "test_checksums",
+                serde_json::to_string(&report.test_checksums).unwrap(),
+            )
+            .file("solution", &report.solution_file)
+            .context(OpenSubmissionFile {
+                filename: report.solution_file.clone(),
+            })?;
+
+        let response = self
+            .http
+            .api_request(Method::POST, &format!("submissions/{}", token))
+            .multipart(form)
+            .send()
+            .context(ServerRequest)?;
+        // The solution is accepted by now, so a response that cannot be read must
+        // not look like a failure that learners would answer by submitting again.
+        let response = Self::expect_ok(response)?;
+        match serde_json::from_reader::<_, SubmittedSolution>(response) {
+            Ok(submitted) => Ok(submitted.data),
+            Err(error) => {
+                eprintln!(
+                    "Warning: The solution was submitted, but the server response could not be read: {}",
+                    error
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    fn grading(&self, token: &str) -> Result<Grading> {
+        self.get_json(&format!("submissions/{}/grading", token), &[])
+    }
+
+    fn download(&self, url: &str, progress: bool) -> Result<Fetched> {
+        download::fetch(&self.http, url, progress)
+    }
+}
+
+/// An in-memory stand-in for the server, recording what the commands send to it.
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    pub struct FakeApi {
+        submissions: Vec<Submission>,
+        replacements: HashMap<String, Submission>,
+        archives: HashMap<String, Vec<u8>>,
+        content_types: HashMap<String, String>,
+        gradings: Mutex<HashMap<String, Vec<Grading>>>,
+        pub downloads: Mutex<Vec<String>>,
+        pub forfeited: Mutex<Vec<String>>,
+        pub submitted: Mutex<Vec<(String, SubmissionReport)>>,
+    }
+
+    fn not_found<T>() -> Result<T> {
+        ExerciseNotFound {
+            message: "Submission not found",
+        }
+        .fail()
+    }
+
+    impl FakeApi {
+        /// Adds a submission. Its archive is served when one is given.
+        pub fn with_submission(mut self, submission: Submission, archive: Option<Vec<u8>>) -> Self {
+            if let Some(archive) = archive {
+                self.archives
+                    .insert(submission.download_url.clone(), archive);
+            }
+            self.submissions.push(submission);
+            self
+        }
+
+        /// Serves the archive at `url` with the given Content-Type.
+        pub fn with_content_type(mut self, url: &str, content_type: &str) -> Self {
+            self.content_types
+                .insert(url.to_owned(), content_type.to_owned());
+            self
+        }
+
+        /// Makes forfeiting `token` hand out `replacement`.
+        pub fn with_replacement(mut self, token: &str, replacement: Submission) -> Self {
+            self.replacements.insert(token.to_owned(), replacement);
+            self
+        }
+
+        /// Makes the grading of `token` go through `gradings`, one per request,
+        /// staying at the last one.
+        pub fn with_gradings(self, token: &str, gradings: Vec<Grading>) -> Self {
+            self.gradings
+                .lock()
+                .unwrap()
+                .insert(token.to_owned(), gradings);
+            self
+        }
+
+        fn next_grading(&self, token: &str) -> Option<Grading> {
+            let mut gradings = self.gradings.lock().unwrap();
+            let gradings = gradings.get_mut(token)?;
+            if gradings.len() > 1 {
+                Some(gradings.remove(0))
+            } else {
+                gradings.first().cloned()
+            }
+        }
+    }
+
+    impl ApiClient for FakeApi {
+        fn list(&self, query: &SubmissionQuery) -> Result<Vec<Submission>> {
+            Ok(self
+                .submissions
+                .iter()
+                .filter(|submission| query.matches(submission))
+                .cloned()
+                .collect())
+        }
+
+        fn get(&self, token: &str) -> Result<Submission> {
+            match self
+                .submissions
+                .iter()
+                .find(|submission| submission.submission_token == token)
+            {
+                Some(submission) => Ok(submission.clone()),
+                None => not_found(),
+            }
+        }
+
+        fn forfeit(&self, token: &str) -> Result<Submission> {
+            self.get(token)?;
+            self.forfeited.lock().unwrap().push(token.to_owned());
+            self.replacements
+                .get(token)
+                .cloned()
+                .context(InvalidForfeit {})
+        }
+
+        fn submit(&self, token: &str, report: SubmissionReport) -> Result<Option<Grading>> {
+            self.get(token)?;
+            self.submitted
+                .lock()
+                .unwrap()
+                .push((token.to_owned(), report));
+            Ok(self.next_grading(token))
+        }
+
+        fn grading(&self, token: &str) -> Result<Grading> {
+            match self.next_grading(token) {
+                Some(grading) => Ok(grading),
+                None => not_found(),
+            }
+        }
+
+        fn download(&self, url: &str, _progress: bool) -> Result<Fetched> {
+            self.downloads.lock().unwrap().push(url.to_owned());
+            match self.archives.get(url) {
+                Some(archive) => Ok(Fetched {
+                    data: archive.clone(),
+                    content_type: self.content_types.get(url).cloned(),
+                }),
+                None => not_found(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Reads a whole request from `reader`, answers it with `200 OK` and `body`,
+    /// and returns the request line.
+    fn answer(reader: &mut BufReader<std::net::TcpStream>, body: &str) -> String {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut content_length = None;
+        let mut chunked = false;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            let header = line.to_lowercase();
+            if let Some(length) = header.strip_prefix("content-length:") {
+                content_length = length.trim().parse::<usize>().ok();
+            }
+            chunked |= header.starts_with("transfer-encoding:") && header.contains("chunked");
+        }
+        if let Some(length) = content_length {
+            reader.read_exact(&mut vec![0; length]).unwrap();
+        } else if chunked {
+            let mut last = String::new();
+            while last != "0\r\n" {
+                last.clear();
+                reader.read_line(&mut last).unwrap();
+                let size = usize::from_str_radix(last.trim(), 16).unwrap();
+                reader.read_exact(&mut vec![0; size + 2]).unwrap();
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        request_line
+    }
+
+    /// Submits a solution to a server answering with `body`.
+    fn submit_answered_with(body: &'static str) -> Result<Option<Grading>> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api = format!("http://{}/api/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            answer(&mut BufReader::new(stream), body)
+        });
+
+        let exercise = tempfile::tempdir().unwrap();
+        let solution_file = exercise.path().join("lib.rs");
+        std::fs::write(&solution_file, "pub fn answer() -> u32 {\n    42\n}\n").unwrap();
+        let report = SubmissionReport {
+            test_output: String::new(),
+            test_errors: String::new(),
+            test_log: String::new(),
+            test_exit_code: Some(0),
+            test_coverage: "1/1".to_owned(),
+            test_checksum: String::new(),
+            test_checksum_algorithm: crate::checksum::DEFAULT_ALGORITHM,
+            test_checksums: Manifest::new(),
+            solution_file,
+        };
+        let client = HttpClient::new(&Config::default(), Url::parse(&api).unwrap()).unwrap();
+        let result = client.submit("token-1", report);
+        assert_eq!(
+            server.join().unwrap(),
+            "POST /api/submissions/token-1 HTTP/1.1\r\n"
+        );
+        result
+    }
+
+    #[test]
+    fn accepts_submissions_without_grading() {
+        let submitted: SubmittedSolution = serde_json::from_str(r#"{"success": true}"#).unwrap();
+        assert!(submitted.data.is_none());
+    }
+
+    #[test]
+    fn returns_the_grading_of_submitted_solutions() {
+        let body = r#"{"data": {"submission_id": "submission-1", "state": "queued"}}"#;
+        let grading = submit_answered_with(body).unwrap().unwrap();
+        assert_eq!(grading.submission_id, "submission-1");
+    }
+
+    #[test]
+    fn accepts_submissions_with_unreadable_responses() {
+        assert!(submit_answered_with("").unwrap().is_none());
+        assert!(submit_answered_with("Thanks!").unwrap().is_none());
+        let body = r#"{"data": {"state": "queued"}}"#;
+        assert!(submit_answered_with(body).unwrap().is_none());
+    }
+
+    #[test]
+    fn maps_error_codes_to_errors() {
+        let body = br#"{"message": "Token expired on 2020-01-01", "code": "token_expired"}"#;
+        let error = server_error(StatusCode::BAD_REQUEST, None, body);
+        assert!(matches!(error, Error::ExpiredToken { message } if message.contains("2020")));
+
+        let body = br#"{"message": "Already graded", "code": "already_graded"}"#;
+        let error = server_error(StatusCode::BAD_REQUEST, None, body);
+        assert!(matches!(error, Error::SubmissionGraded { .. }));
+    }
+
+    #[test]
+    fn falls_back_to_the_status_code() {
+        let error = server_error(StatusCode::NOT_FOUND, None, b"<html>Not Found</html>");
+        assert!(matches!(error, Error::ExerciseNotFound { message } if message == "Not Found"));
+
+        let retry_after = Some(Duration::from_secs(30));
+        let error = server_error(StatusCode::TOO_MANY_REQUESTS, retry_after, b"");
+        assert!(matches!(
+            error,
+            Error::RateLimited {
+                retry_after: Some(30)
+            }
+        ));
+        assert!(error.to_string().contains("in 30 seconds"));
+    }
+
+    #[test]
+    fn reports_field_errors() {
+        let body = br#"{
+            "message": "The submission is invalid",
+            "code": "invalid_submission",
+            "errors": {"solution": ["is empty"], "test_output": ["is missing", "is too long"]}
+        }"#;
+        let error = server_error(StatusCode::BAD_REQUEST, None, body);
+        assert_eq!(
+            error.to_string(),
+            "The Sharpener server refused the request (400 Bad Request): The submission is invalid\n  solution: is empty\n  test_output: is missing, is too long"
+        );
+    }
+
+    #[test]
+    fn keeps_status_when_nothing_is_explained() {
+        let error = server_error(StatusCode::BAD_GATEWAY, None, b"");
+        assert!(matches!(
+            error,
+            Error::InvalidAPIResponse {
+                received: StatusCode::BAD_GATEWAY,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,534 @@
+use crate::checksum;
+use crate::error::*;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Upper bound for the unpacked size of an exercise, to stop decompression bombs.
+const MAX_UNPACKED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Checks a downloaded archive against the size and checksum announced by the
+/// server, when it provides them.
+pub fn verify(
+    archive: &[u8],
+    expected_checksum: Option<&str>,
+    expected_size: Option<u64>,
+) -> Result<()> {
+    if let Some(expected_size) = expected_size {
+        ensure!(
+            archive.len() as u64 == expected_size,
+            InvalidDownload {
+                reason: format!(
+                    "expected {} bytes, received {}",
+                    expected_size,
+                    archive.len()
+                )
+            }
+        );
+    }
+    if let Some(expected_checksum) = expected_checksum {
+        ensure!(
+            checksum::bytes_match(archive, expected_checksum)?,
+            InvalidDownload {
+                reason: "the archive checksum does not match"
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Container formats exercises can be distributed in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime {
+            "application/gzip" | "application/x-gzip" | "application/x-tar+gzip" => {
+                Some(Self::TarGz)
+            }
+            "application/zstd" | "application/x-zstd" => Some(Self::TarZst),
+            "application/zip" | "application/x-zip-compressed" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    fn from_magic_bytes(archive: &[u8]) -> Option<Self> {
+        if archive.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if archive.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Self::TarZst)
+        } else if archive.starts_with(b"PK\x03\x04") || archive.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Detects the format from the Content-Type the archive was served with,
+    /// falling back to its magic bytes.
+    pub fn detect(archive: &[u8], content_type: Option<&str>) -> Result<Self> {
+        content_type
+            .and_then(Self::from_content_type)
+            .or_else(|| Self::from_magic_bytes(archive))
+            .context(InvalidDownload {
+                reason: "the archive format is not supported",
+            })
+    }
+
+    fn unpacker(self) -> Box<dyn Unpacker> {
+        match self {
+            Self::TarGz => Box::new(TarUnpacker {
+                decoder: |archive| Ok(Box::new(GzDecoder::new(archive))),
+            }),
+            Self::TarZst => Box::new(TarUnpacker {
+                decoder: |archive| Ok(Box::new(zstd::stream::read::Decoder::new(archive)?)),
+            }),
+            Self::Zip => Box::new(ZipUnpacker),
+        }
+    }
+}
+
+/// An exercise archive, along with the format it was detected to be in.
+pub struct Archive {
+    pub data: Vec<u8>,
+    pub format: ArchiveFormat,
+}
+
+impl Archive {
+    pub fn new(data: Vec<u8>, content_type: Option<&str>) -> Result<Self> {
+        let format = ArchiveFormat::detect(&data, content_type)?;
+        Ok(Self { data, format })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Directory,
+    Link,
+    /// Entries describing other entries, which create nothing on disk.
+    Metadata,
+    Other,
+}
+
+struct EntryInfo {
+    path: PathBuf,
+    kind: EntryKind,
+    mode: Option<u32>,
+}
+
+type EntryVisitor<'a> = dyn FnMut(EntryInfo, &mut dyn Read) -> Result<()> + 'a;
+
+/// Reads the entries of one archive format, leaving validation and writing to `unpack`.
+trait Unpacker {
+    fn visit_entries(&self, archive: &[u8], visit: &mut EntryVisitor) -> Result<()>;
+}
+
+struct TarUnpacker {
+    decoder: for<'a> fn(&'a [u8]) -> io::Result<Box<dyn Read + 'a>>,
+}
+
+impl Unpacker for TarUnpacker {
+    fn visit_entries(&self, archive: &[u8], visit: &mut EntryVisitor) -> Result<()> {
+        let decoded = (self.decoder)(archive).context(UnpackTar {})?;
+        let mut archive = tar::Archive::new(decoded);
+        for entry in archive.entries().context(UnpackTar {})? {
+            let mut entry = entry.context(UnpackTar {})?;
+            let kind = match entry.header().entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+                tar::EntryType::Directory => EntryKind::Directory,
+                tar::EntryType::Symlink | tar::EntryType::Link => EntryKind::Link,
+                tar::EntryType::XGlobalHeader | tar::EntryType::XHeader => EntryKind::Metadata,
+                _ => EntryKind::Other,
+            };
+            let info = EntryInfo {
+                path: entry.path().context(UnpackTar {})?.into_owned(),
+                kind,
+                mode: entry.header().mode().ok(),
+            };
+            visit(info, &mut entry)?;
+        }
+        Ok(())
+    }
+}
+
+struct ZipUnpacker;
+
+impl Unpacker for ZipUnpacker {
+    fn visit_entries(&self, archive: &[u8], visit: &mut EntryVisitor) -> Result<()> {
+        const FILE_TYPE_MASK: u32 = 0o170_000;
+        const SYMLINK: u32 = 0o120_000;
+
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(archive)).context(UnpackZip {})?;
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).context(UnpackZip {})?;
+            let mode = file.unix_mode();
+            let kind = if mode.map(|mode| mode & FILE_TYPE_MASK) == Some(SYMLINK) {
+                EntryKind::Link
+            } else if file.name().ends_with('/') {
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            };
+            let info = EntryInfo {
+                path: PathBuf::from(file.name()),
+                kind,
+                mode: mode.map(|mode| mode & 0o777),
+            };
+            visit(info, &mut file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Resolves where an entry is unpacked, refusing anything that is not a plain file
+/// or directory inside `root`, or that would replace an existing file.
+fn entry_destination(entry: &EntryInfo, destination: &Path, root: &str) -> Result<Option<PathBuf>> {
+    let path = &entry.path;
+    let unsafe_entry = |reason: &str| UnsafeArchive {
+        path: path.clone(),
+        reason: reason.to_owned(),
+    };
+
+    match entry.kind {
+        EntryKind::File | EntryKind::Directory => (),
+        EntryKind::Metadata => return Ok(None),
+        EntryKind::Link => return unsafe_entry("links are not allowed").fail(),
+        EntryKind::Other => return unsafe_entry("only files and directories are allowed").fail(),
+    }
+
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                return unsafe_entry("parent directories are not allowed").fail()
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return unsafe_entry("absolute paths are not allowed").fail()
+            }
+        }
+    }
+    ensure!(
+        relative.starts_with(root),
+        unsafe_entry(&format!("entries must be inside {}/", root))
+    );
+
+    let target = destination.join(relative);
+    ensure!(
+        entry.kind == EntryKind::Directory || fs::symlink_metadata(&target).is_err(),
+        unsafe_entry("a file already exists at this path")
+    );
+    Ok(Some(target))
+}
+
+fn write_file(target: &Path, contents: &mut dyn Read, mode: Option<u32>) -> Result<()> {
+    fs::create_dir_all(target.parent().unwrap()).context(UnpackTar {})?;
+    let mut file = fs::File::create(target).context(UnpackTar {})?;
+    io::copy(contents, &mut file).context(UnpackTar {})?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = mode {
+            let permissions = fs::Permissions::from_mode(mode & 0o777);
+            fs::set_permissions(target, permissions).context(UnpackTar {})?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// Unpacks the exercise archive, whose entries must all live under `root`, into
+/// `destination`. Nothing is written unless every entry passes validation and
+/// the whole archive unpacks within `MAX_UNPACKED_SIZE`.
+pub fn unpack(archive: &Archive, destination: &Path, root: &str) -> Result<()> {
+    unpack_within(archive, destination, root, MAX_UNPACKED_SIZE)
+}
+
+fn unpack_within(archive: &Archive, destination: &Path, root: &str, max_size: u64) -> Result<()> {
+    let unpacker = archive.format.unpacker();
+    let archive = &archive.data[..];
+
+    let mut unpacked_size = 0;
+    unpacker.visit_entries(archive, &mut |entry, contents| {
+        entry_destination(&entry, destination, root)?;
+        let mut limited = contents.take(max_size - unpacked_size + 1);
+        unpacked_size += io::copy(&mut limited, &mut io::sink()).context(UnpackTar {})?;
+        ensure!(
+            unpacked_size <= max_size,
+            InvalidDownload {
+                reason: format!(
+                    "the exercise is larger than {} MiB once unpacked",
+                    max_size / 1024 / 1024
+                )
+            }
+        );
+        Ok(())
+    })?;
+
+    unpacker.visit_entries(archive, &mut |entry, contents| match entry_destination(
+        &entry,
+        destination,
+        root,
+    )? {
+        Some(target) if entry.kind == EntryKind::Directory => {
+            fs::create_dir_all(&target).context(UnpackTar {})
+        }
+        Some(target) => write_file(&target, contents, entry.mode),
+        None => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn header(path: &str, entry_type: tar::EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_old();
+        // Written by hand because `set_path` refuses the paths under test.
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_cksum();
+        header
+    }
+
+    fn build_archive(entries: Vec<(tar::Header, &[u8])>) -> Vec<u8> {
+        let encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (header, data) in entries {
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn file(path: &str, data: &'static [u8]) -> (tar::Header, &'static [u8]) {
+        (
+            header(path, tar::EntryType::Regular, data.len() as u64),
+            data,
+        )
+    }
+
+    fn assert_refused(archive: &[u8]) {
+        let destination = tempfile::tempdir().unwrap();
+        let archive = Archive::new(archive.to_vec(), None).unwrap();
+        let result = unpack(&archive, destination.path(), "exercise");
+        assert!(matches!(result, Err(Error::UnsafeArchive { .. })));
+        let written = fs::read_dir(destination.path()).unwrap().count();
+        assert_eq!(written, 0, "nothing should be written");
+    }
+
+    #[test]
+    fn unpacks_valid_archive() {
+        let archive = build_archive(vec![
+            (header("exercise/", tar::EntryType::Directory, 0), &[]),
+            file("exercise/src/lib.rs", b"pub fn answer() {}"),
+            file("./exercise/tests/tests.rs", b"#[test] fn it_works() {}"),
+        ]);
+        let archive = Archive::new(archive, None).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        unpack(&archive, destination.path(), "exercise").unwrap();
+        let solution = fs::read(destination.path().join("exercise/src/lib.rs")).unwrap();
+        assert_eq!(solution, b"pub fn answer() {}");
+        assert!(destination.path().join("exercise/tests/tests.rs").is_file());
+    }
+
+    #[test]
+    fn verifies_size_and_checksum() {
+        let archive = b"exercise archive";
+        let sha256 = checksum::checksum_bytes(archive, checksum::Algorithm::Sha256);
+        verify(archive, Some(&sha256), Some(archive.len() as u64)).unwrap();
+
+        let truncated = verify(&archive[1..], Some(&sha256), None);
+        assert!(matches!(truncated, Err(Error::InvalidDownload { .. })));
+        let wrong_size = verify(archive, None, Some(archive.len() as u64 + 1));
+        assert!(matches!(wrong_size, Err(Error::InvalidDownload { .. })));
+    }
+
+    #[test]
+    fn accepts_legacy_md5_checksums() {
+        let archive = b"exercise archive";
+        let md5 = format!("{:x}", md5::compute(archive));
+        assert!(!md5.contains(':'));
+        verify(archive, Some(&md5), None).unwrap();
+        verify(archive, Some(&md5.to_uppercase()), None).unwrap();
+        let result = verify(b"another archive", Some(&md5), None);
+        assert!(matches!(result, Err(Error::InvalidDownload { .. })));
+    }
+
+    #[test]
+    fn refuses_archives_too_large_once_unpacked() {
+        let archive = build_archive(vec![
+            file("exercise/src/lib.rs", b"0123456789"),
+            file("exercise/tests/tests.rs", b"0123456789"),
+        ]);
+        let archive = Archive::new(archive, None).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        let result = unpack_within(&archive, destination.path(), "exercise", 15);
+        assert!(matches!(result, Err(Error::InvalidDownload { .. })));
+        assert_eq!(fs::read_dir(destination.path()).unwrap().count(), 0);
+
+        unpack_within(&archive, destination.path(), "exercise", 20).unwrap();
+    }
+
+    #[test]
+    fn refuses_parent_directories() {
+        assert_refused(&build_archive(vec![
+            file("exercise/src/lib.rs", b"fine"),
+            file("exercise/../../evil.sh", b"rm -rf ~"),
+        ]));
+    }
+
+    #[test]
+    fn refuses_absolute_paths() {
+        assert_refused(&build_archive(vec![file("/tmp/evil.sh", b"rm -rf ~")]));
+    }
+
+    #[test]
+    fn refuses_entries_outside_the_exercise() {
+        assert_refused(&build_archive(vec![file("other/src/lib.rs", b"fine")]));
+    }
+
+    #[test]
+    fn refuses_links() {
+        let mut symlink = header("exercise/link", tar::EntryType::Symlink, 0);
+        symlink.set_link_name("/etc/passwd").unwrap();
+        symlink.set_cksum();
+        assert_refused(&build_archive(vec![(symlink, &[])]));
+
+        let mut hardlink = header("exercise/link", tar::EntryType::Link, 0);
+        hardlink.set_link_name("exercise/src/lib.rs").unwrap();
+        hardlink.set_cksum();
+        assert_refused(&build_archive(vec![(hardlink, &[])]));
+    }
+
+    #[test]
+    fn refuses_devices() {
+        assert_refused(&build_archive(vec![(
+            header("exercise/device", tar::EntryType::Char, 0),
+            &[],
+        )]));
+    }
+
+    #[test]
+    fn refuses_to_overwrite_existing_files() {
+        let archive = build_archive(vec![file("exercise/src/lib.rs", b"starting point")]);
+        let archive = Archive::new(archive, None).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        let solution = destination.path().join("exercise/src/lib.rs");
+        fs::create_dir_all(solution.parent().unwrap()).unwrap();
+        fs::write(&solution, b"my solution").unwrap();
+
+        let result = unpack(&archive, destination.path(), "exercise");
+        assert!(matches!(result, Err(Error::UnsafeArchive { .. })));
+        assert_eq!(fs::read(&solution).unwrap(), b"my solution");
+    }
+
+    fn build_zip(entries: Vec<(&str, Option<u32>, &[u8])>) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (path, mode, data) in entries {
+            let mut options = zip::write::FileOptions::default();
+            if let Some(mode) = mode {
+                options = options.unix_permissions(mode);
+            }
+            writer.start_file(path, options).unwrap();
+            io::Write::write_all(&mut writer, data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn detects_format_from_content_type_then_magic_bytes() {
+        let zip = build_zip(vec![("exercise/src/lib.rs", None, b"")]);
+        assert_eq!(
+            ArchiveFormat::detect(&zip, None).unwrap(),
+            ArchiveFormat::Zip
+        );
+        assert_eq!(
+            ArchiveFormat::detect(&zip, Some("application/zstd")).unwrap(),
+            ArchiveFormat::TarZst
+        );
+        assert_eq!(
+            ArchiveFormat::detect(&zip, Some("application/octet-stream")).unwrap(),
+            ArchiveFormat::Zip
+        );
+        assert!(ArchiveFormat::detect(b"plain text", None).is_err());
+    }
+
+    #[test]
+    fn unpacks_zip_archive() {
+        let archive = build_zip(vec![
+            ("exercise/src/lib.rs", None, b"pub fn answer() {}"),
+            ("exercise/run.sh", Some(0o755), b"#!/bin/sh"),
+        ]);
+        let archive = Archive::new(archive, None).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        unpack(&archive, destination.path(), "exercise").unwrap();
+
+        let solution = fs::read(destination.path().join("exercise/src/lib.rs")).unwrap();
+        assert_eq!(solution, b"pub fn answer() {}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let script = fs::metadata(destination.path().join("exercise/run.sh")).unwrap();
+            assert_eq!(script.permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn unpacks_zstd_archive() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let (header, data) = file("exercise/src/lib.rs", b"pub fn answer() {}");
+        builder.append(&header, data).unwrap();
+        let tarball = builder.into_inner().unwrap();
+        let archive = zstd::stream::encode_all(&tarball[..], 0).unwrap();
+        let archive = Archive::new(archive, None).unwrap();
+
+        let destination = tempfile::tempdir().unwrap();
+        unpack(&archive, destination.path(), "exercise").unwrap();
+        let solution = fs::read(destination.path().join("exercise/src/lib.rs")).unwrap();
+        assert_eq!(solution, b"pub fn answer() {}");
+    }
+
+    #[test]
+    fn unpacks_in_the_format_it_was_served_as() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let (header, data) = file("exercise/src/lib.rs", b"pub fn answer() {}");
+        builder.append(&header, data).unwrap();
+        let tarball = builder.into_inner().unwrap();
+        // A leading skippable frame hides the zstd magic bytes.
+        let mut data = vec![0x50, 0x2a, 0x4d, 0x18, 0, 0, 0, 0];
+        data.extend(zstd::stream::encode_all(&tarball[..], 0).unwrap());
+        assert!(Archive::new(data.clone(), None).is_err());
+
+        let archive = Archive::new(data, Some("application/zstd")).unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        unpack(&archive, destination.path(), "exercise").unwrap();
+        assert!(destination.path().join("exercise/src/lib.rs").is_file());
+    }
+
+    #[test]
+    fn refuses_unsafe_zip_entries() {
+        assert_refused(&build_zip(vec![
+            ("exercise/src/lib.rs", None, b""),
+            ("exercise/../../escape.rs", None, b""),
+        ]));
+        assert_refused(&build_zip(vec![("/exercise/src/lib.rs", None, b"")]));
+        assert_refused(&build_zip(vec![("other/src/lib.rs", None, b"")]));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::api::ApiClient;
+use crate::cache::ArchiveCache;
+use crate::config::Config;
+use crate::error::*;
+use crate::output::OutputFormat;
+use crate::submission::{ExistingExercise, Submission, SubmissionQuery, SubmissionStatus};
+use crate::table;
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::Mutex;
+use std::thread;
+
+/// How many exercises are downloaded at the same time.
+const DOWNLOAD_WORKERS: usize = 4;
+
+enum Outcome {
+    Downloaded(PathBuf),
+    Skipped(PathBuf),
+    Failed(String),
+}
+
+struct Download {
+    submission: Submission,
+    destination: PathBuf,
+}
+
+fn download_one(api: &dyn ApiClient, cache: &ArchiveCache, download: &Download) -> Outcome {
+    let exercise_dir = download
+        .destination
+        .join(&download.submission.exercise_name);
+    if exercise_dir.exists() {
+        return Outcome::Skipped(exercise_dir);
+    }
+    match download.submission.download(
+        api,
+        cache,
+        &download.destination,
+        ExistingExercise::Refuse,
+        false,
+    ) {
+        Ok(path) => Outcome::Downloaded(path),
+        Err(error) => Outcome::Failed(error.to_string()),
+    }
+}
+
+fn print_results(
+    results: &[(Download, Outcome)],
+    output: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let data: Vec<_> = results
+        .iter()
+        .map(|(download, outcome)| {
+            let (result, exercise_dir, error) = match outcome {
+                Outcome::Downloaded(path) => ("downloaded", Some(path), None),
+                Outcome::Skipped(path) => ("skipped", Some(path), None),
+                Outcome::Failed(reason) => ("failed", None, Some(reason)),
+            };
+            json!({
+                "exercise_name": download.submission.exercise_name,
+                "exercise_language": download.submission.exercise_language.as_str(),
+                "submission_token": download.submission.submission_token,
+                "result": result,
+                "exercise_dir": exercise_dir,
+                "error": error,
+            })
+        })
+        .collect();
+    output.print(out, "download", &data, || {
+        let rows: Vec<Vec<String>> = results
+            .iter()
+            .map(|(download, outcome)| {
+                let result = match outcome {
+                    Outcome::Downloaded(path) => format!("downloaded to {}", path.display()),
+                    Outcome::Skipped(path) => format!("already present at {}", path.display()),
+                    Outcome::Failed(reason) => format!("failed: {}", reason),
+                };
+                vec![
+                    download.submission.exercise_name.clone(),
+                    download.submission.exercise_language.to_string(),
+                    result,
+                ]
+            })
+            .collect();
+        let table = table::render_table(&["Exercise", "Language", "Result"], &rows);
+        table.trim_end().to_owned()
+    })
+}
+
+/// Downloads every pending exercise matching `query`, a few at a time, and
+/// reports the result for each of them.
+pub fn download_pending(
+    api: &dyn ApiClient,
+    config: &Config,
+    cache: &ArchiveCache,
+    dir: Option<&Path>,
+    query: &SubmissionQuery,
+    output: OutputFormat,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let query = SubmissionQuery {
+        status: Some(SubmissionStatus::Pending),
+        ..query.clone()
+    };
+    let pending = Submission::list(api, &query)?;
+    let queue: Vec<Download> = pending
+        .into_iter()
+        .map(|submission| {
+            let destination = dir
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| config.download_dir(&submission.exercise_language));
+            Download {
+                submission,
+                destination,
+            }
+        })
+        .collect();
+
+    if queue.is_empty() {
+        output.print(out, "download", &[] as &[()], || {
+            "There are no pending exercises to download".to_owned()
+        })?;
+        return Ok(());
+    }
+
+    let total = queue.len();
+    let queue = Mutex::new(queue.into_iter().enumerate());
+    let (sender, receiver) = channel();
+    thread::scope(|scope| {
+        for _ in 0..DOWNLOAD_WORKERS.min(total) {
+            let queue = &queue;
+            let sender = sender.clone();
+            scope.spawn(move || loop {
+                let next = queue.lock().unwrap().next();
+                match next {
+                    Some((index, download)) => {
+                        let outcome = download_one(api, cache, &download);
+                        if sender.send((index, download, outcome)).is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            });
+        }
+    });
+    drop(sender);
+
+    let mut results: Vec<_> = receiver.iter().collect();
+    results.sort_by_key(|(index, _, _)| *index);
+    let results: Vec<_> = results
+        .into_iter()
+        .map(|(_, download, outcome)| (download, outcome))
+        .collect();
+    print_results(&results, output, out)?;
+
+    let failed = results
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+        .count();
+    ensure!(failed == 0, BulkDownload { failed, total });
+    Ok(())
+}
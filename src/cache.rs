@@ -0,0 +1,254 @@
+use crate::archive::{Archive, ArchiveFormat};
+use crate::checksum::{self, Algorithm};
+use crate::error::*;
+use crate::reset;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Default upper bound for the total size of cached archives.
+pub const DEFAULT_LIMIT_MB: u64 = 512;
+
+/// Describes a cached archive. Stored next to it as `<digest>.json`.
+#[derive(Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub exercise_name: String,
+    pub download_url: String,
+    pub last_used: u64,
+
+    /// Missing for archives cached by older versions, which are detected again.
+    #[serde(default)]
+    pub format: Option<ArchiveFormat>,
+
+    #[serde(skip)]
+    pub digest: String,
+
+    #[serde(skip)]
+    pub size: u64,
+}
+
+/// Exercise archives stored under the user's cache directory, addressed by their
+/// SHA-256 digest.
+#[derive(Clone)]
+pub struct ArchiveCache {
+    root: PathBuf,
+    limit: u64,
+}
+
+impl ArchiveCache {
+    pub fn new(limit_mb: u64) -> Self {
+        let root = dirs::cache_dir()
+            .unwrap_or_else(env::temp_dir)
+            .join("sharpener/archives");
+        Self::in_dir(root, limit_mb)
+    }
+
+    pub fn in_dir(root: PathBuf, limit_mb: u64) -> Self {
+        Self {
+            root,
+            limit: limit_mb * 1024 * 1024,
+        }
+    }
+
+    fn archive_path(&self, digest: &str) -> PathBuf {
+        self.root.join(format!("{}.archive", digest))
+    }
+
+    fn entry_path(&self, digest: &str) -> PathBuf {
+        self.root.join(format!("{}.json", digest))
+    }
+
+    fn touch(&self, entry: &mut CacheEntry) -> Result<()> {
+        entry.last_used = reset::timestamp();
+        let file = File::create(self.entry_path(&entry.digest)).context(IOError {})?;
+        serde_json::to_writer_pretty(file, entry).context(WriteCacheEntry {})
+    }
+
+    /// Stores `archive` and returns its checksum. Least recently used archives are
+    /// evicted afterwards to keep the cache within its size limit, sparing this one.
+    pub fn store(
+        &self,
+        archive: &Archive,
+        exercise_name: &str,
+        download_url: &str,
+    ) -> Result<String> {
+        let checksum = checksum::checksum_bytes(&archive.data, Algorithm::Sha256);
+        let digest = checksum::parse(&checksum)?.1.to_owned();
+        fs::create_dir_all(&self.root).context(IOError {})?;
+        fs::write(self.archive_path(&digest), &archive.data).context(IOError {})?;
+
+        let mut entry = CacheEntry {
+            exercise_name: exercise_name.to_owned(),
+            download_url: download_url.to_owned(),
+            last_used: 0,
+            format: Some(archive.format),
+            digest,
+            size: archive.data.len() as u64,
+        };
+        self.touch(&mut entry)?;
+        self.evict(self.limit, Some(&entry.digest))?;
+        Ok(checksum)
+    }
+
+    fn load_entry(&self, mut entry: CacheEntry) -> Option<Archive> {
+        let data = fs::read(self.archive_path(&entry.digest)).ok()?;
+        let expected = format!("sha256:{}", entry.digest);
+        if !checksum::bytes_match(&data, &expected).unwrap_or(false) {
+            return None;
+        }
+        let archive = match entry.format {
+            Some(format) => Archive { data, format },
+            None => Archive::new(data, None).ok()?,
+        };
+        // Failing to record the access only affects eviction order.
+        let _ = self.touch(&mut entry);
+        Some(archive)
+    }
+
+    /// Looks an archive up by the checksum announced for it. Archives whose
+    /// content no longer matches are ignored.
+    pub fn get(&self, expected_checksum: &str) -> Option<Archive> {
+        let (algorithm, digest) = checksum::parse(expected_checksum).ok()?;
+        if algorithm != Algorithm::Sha256 {
+            return None;
+        }
+        let digest = digest.to_lowercase();
+        let entry = self
+            .entries()
+            .ok()?
+            .into_iter()
+            .find(|entry| entry.digest == digest)?;
+        self.load_entry(entry)
+    }
+
+    /// Looks up the most recent archive downloaded from `download_url`, for when
+    /// the server cannot be reached.
+    pub fn get_by_url(&self, download_url: &str) -> Option<Archive> {
+        let entry = self
+            .entries()
+            .ok()?
+            .into_iter()
+            .filter(|entry| entry.download_url == download_url)
+            .max_by_key(|entry| entry.last_used)?;
+        self.load_entry(entry)
+    }
+
+    /// Lists cached archives, most recently used first.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        if !self.root.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.root).context(IOError {})? {
+            let path = file.context(IOError {})?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+
+            let digest = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let size = match fs::metadata(self.archive_path(&digest)) {
+                Ok(metadata) => metadata.len(),
+                Err(_) => continue,
+            };
+            let parsed = File::open(&path)
+                .ok()
+                .and_then(|file| serde_json::from_reader::<_, CacheEntry>(file).ok());
+            if let Some(mut entry) = parsed {
+                entry.digest = digest;
+                entry.size = size;
+                entries.push(entry);
+            }
+        }
+        entries.sort_by_key(|entry| Reverse(entry.last_used));
+        Ok(entries)
+    }
+
+    fn remove(&self, digest: &str) -> Result<()> {
+        for path in [self.archive_path(digest), self.entry_path(digest)].iter() {
+            match fs::remove_file(path) {
+                // Another process may be pruning the cache at the same time.
+                Err(error) if error.kind() != ErrorKind::NotFound => {
+                    return Err(error).context(IOError {})
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Evicts the least recently used archives until the cache fits in `limit`
+    /// bytes, and returns how many were removed.
+    pub fn prune(&self, limit: u64) -> Result<usize> {
+        self.evict(limit, None)
+    }
+
+    fn evict(&self, limit: u64, keep: Option<&str>) -> Result<usize> {
+        let entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+        let mut removed = 0;
+        for entry in entries.iter().rev() {
+            if total <= limit {
+                break;
+            }
+            if Some(entry.digest.as_str()) == keep {
+                continue;
+            }
+            self.remove(&entry.digest)?;
+            total -= entry.size;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        if self.root.is_dir() {
+            fs::remove_dir_all(&self.root).context(IOError {})?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(data: &[u8]) -> Archive {
+        Archive {
+            data: data.to_vec(),
+            format: ArchiveFormat::TarGz,
+        }
+    }
+
+    #[test]
+    fn keeps_the_archive_just_stored() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = ArchiveCache::in_dir(root.path().to_path_buf(), 0);
+        cache
+            .store(&archive(b"first"), "first", "first.tar.gz")
+            .unwrap();
+        let second = cache
+            .store(&archive(b"second"), "second", "second.tar.gz")
+            .unwrap();
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].exercise_name, "second");
+        assert_eq!(cache.get(&second).unwrap().data, b"second");
+    }
+
+    #[test]
+    fn ignores_archives_removed_meanwhile() {
+        let root = tempfile::tempdir().unwrap();
+        let cache = ArchiveCache::in_dir(root.path().to_path_buf(), 64);
+        cache.remove("0123abcd").unwrap();
+    }
+}
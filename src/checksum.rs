@@ -0,0 +1,156 @@
+use crate::error::*;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Checksums are stored as `<algorithm>:<hex digest>`. Values without a prefix
+/// were written by older versions of the CLI and are MD5 digests.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "md5" => Some(Self::Md5),
+            "sha256" => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+}
+
+pub static DEFAULT_ALGORITHM: Algorithm = Algorithm::Sha256;
+
+enum Hasher {
+    Md5(md5::Context),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Md5 => Self::Md5(md5::Context::new()),
+            Algorithm::Sha256 => Self::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(context) => context.consume(data),
+            Self::Sha256(hasher) => hasher.input(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Self::Md5(context) => format!("{:x}", context.compute()),
+            Self::Sha256(hasher) => format!("{:x}", hasher.result()),
+        }
+    }
+}
+
+fn prefixed(algorithm: Algorithm, digest: String) -> String {
+    format!("{}:{}", algorithm.as_str(), digest)
+}
+
+pub fn parse(checksum: &str) -> Result<(Algorithm, &str)> {
+    match checksum.find(':') {
+        None => Ok((Algorithm::Md5, checksum)),
+        Some(index) => {
+            let name = &checksum[..index];
+            let algorithm = Algorithm::from_name(name).context(UnsupportedChecksum {
+                algorithm: name.to_owned(),
+            })?;
+            Ok((algorithm, &checksum[index + 1..]))
+        }
+    }
+}
+
+/// The hex digest of `data`, without the algorithm prefix.
+pub fn hex_digest(data: &[u8], algorithm: Algorithm) -> String {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finish()
+}
+
+pub fn checksum_bytes(data: &[u8], algorithm: Algorithm) -> String {
+    prefixed(algorithm, hex_digest(data, algorithm))
+}
+
+pub fn checksum_file(path: &Path, algorithm: Algorithm) -> Result<String> {
+    let file = File::open(path).with_context(|| OpenSubmissionFile {
+        filename: path.to_path_buf(),
+    })?;
+    let mut buffed_file = BufReader::new(file);
+
+    let mut hasher = Hasher::new(algorithm);
+
+    loop {
+        let buffer = buffed_file.fill_buf().with_context(|| ReadSubmissionFile {
+            filename: path.to_path_buf(),
+        })?;
+
+        if buffer.is_empty() {
+            return Ok(prefixed(algorithm, hasher.finish()));
+        }
+
+        hasher.update(buffer);
+        let consumed = buffer.len();
+        buffed_file.consume(consumed);
+    }
+}
+
+/// Checks `path` against `expected` using whichever algorithm produced it.
+pub fn file_matches(path: &Path, expected: &str) -> Result<bool> {
+    let (algorithm, digest) = parse(expected)?;
+    let actual = checksum_file(path, algorithm)?;
+    Ok(parse(&actual)?.1.eq_ignore_ascii_case(digest))
+}
+
+pub fn bytes_match(data: &[u8], expected: &str) -> Result<bool> {
+    let (algorithm, digest) = parse(expected)?;
+    let actual = checksum_bytes(data, algorithm);
+    Ok(parse(&actual)?.1.eq_ignore_ascii_case(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn prefixes_checksums_with_their_algorithm() {
+        let checksum = checksum_bytes(b"abc", Algorithm::Sha256);
+        assert_eq!(checksum, format!("sha256:{}", ABC_SHA256));
+        assert_eq!(parse(&checksum).unwrap(), (Algorithm::Sha256, ABC_SHA256));
+        let uppercase = format!("sha256:{}", ABC_SHA256.to_uppercase());
+        assert!(bytes_match(b"abc", &uppercase).unwrap());
+        assert!(!bytes_match(b"abd", &checksum).unwrap());
+    }
+
+    #[test]
+    fn reads_unprefixed_checksums_as_md5() {
+        let digest = "900150983cd24fb0d6963f7d28e17f72";
+        assert_eq!(parse(digest).unwrap(), (Algorithm::Md5, digest));
+        assert!(bytes_match(b"abc", digest).unwrap());
+    }
+
+    #[test]
+    fn refuses_unknown_algorithms() {
+        let result = parse("crc32:352441c2");
+        assert!(
+            matches!(result, Err(Error::UnsupportedChecksum { algorithm }) if algorithm == "crc32")
+        );
+    }
+}
@@ -1,26 +1,54 @@
+use crate::cache::{self, ArchiveCache};
 use crate::error::*;
+use crate::language::Language;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::fs::File;
 use std::path::PathBuf;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Config {
     pub token: String,
+
+    /// Exercises are downloaded to `<workspace>/<language>/<exercise>` when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<PathBuf>,
+
+    /// Size limit of the archive cache, in MiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_limit_mb: Option<u64>,
+
+    /// Seconds to wait for a connection to the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Seconds to wait for the server to respond or send more data.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>,
 }
 
 impl Config {
-    fn get_path() -> PathBuf {
+    pub fn get_path() -> PathBuf {
         let home = env::var("HOME").map(PathBuf::from).unwrap();
         home.join(".sharpener-config")
     }
 
-    pub fn create(token: String) -> Result<Self> {
+    pub fn save(&self) -> Result<()> {
         let filename = Self::get_path();
         let file = File::create(&filename).context(OpenConfigFile { filename })?;
-        let config = Self { token };
-        serde_json::to_writer_pretty(file, &config).context(ConfigWrite {})?;
-        Ok(config)
+        serde_json::to_writer_pretty(file, self).context(ConfigWrite {})
+    }
+
+    /// Directory where an exercise in `language` should be downloaded to.
+    pub fn download_dir(&self, language: &Language) -> PathBuf {
+        match self.workspace.as_ref() {
+            Some(workspace) => workspace.join(language.as_str()),
+            None => PathBuf::from("./"),
+        }
+    }
+
+    pub fn archive_cache(&self) -> ArchiveCache {
+        ArchiveCache::new(self.cache_limit_mb.unwrap_or(cache::DEFAULT_LIMIT_MB))
     }
 
     pub fn load() -> Result<Self> {
@@ -0,0 +1,345 @@
+use crate::checksum::{self, Algorithm};
+use crate::error::*;
+use crate::http::Http;
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::header::{CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Method, Response, StatusCode};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Partial downloads are kept here, keyed by URL, so they can be resumed.
+fn partial_path(url: &str) -> PathBuf {
+    let key = checksum::checksum_bytes(url.as_bytes(), Algorithm::Sha256).replace(':', "-");
+    dirs::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join("sharpener/partial")
+        .join(key)
+}
+
+fn progress_bar(total: Option<u64>, visible: bool) -> ProgressBar {
+    if !visible || !atty::is(atty::Stream::Stderr) {
+        return ProgressBar::hidden();
+    }
+    match total {
+        Some(total) => {
+            let bar = ProgressBar::new(total);
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{bytes}/{total_bytes} [{bar:40}] {bytes_per_sec}, {eta} left")
+                    .progress_chars("=> "),
+            );
+            bar
+        }
+        None => {
+            let bar = ProgressBar::new_spinner();
+            bar.set_style(
+                ProgressStyle::default_spinner().template("{spinner} {bytes} downloaded"),
+            );
+            bar
+        }
+    }
+}
+
+/// Where the validator of a partial download is kept, see `validator`.
+fn validator_path(partial: &Path) -> PathBuf {
+    partial.with_extension("validator")
+}
+
+/// Identifies the version of the file being downloaded, so that a resumed download
+/// only continues the same version. Weak ETags cannot be used for this.
+fn validator(response: &Response) -> Option<String> {
+    let header = |name| response.headers().get(name)?.to_str().ok();
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(str::to_owned)
+}
+
+/// Where the content of a partial response starts, from `bytes <start>-<end>/<size>`.
+fn range_start(response: &Response) -> Option<u64> {
+    let range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = range.strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+/// Forgets a partial download so the next attempt starts over.
+fn discard_partial(partial: &Path) -> Result<()> {
+    for path in [partial.to_path_buf(), validator_path(partial)].iter() {
+        if path.exists() {
+            fs::remove_file(path).context(IOError {})?;
+        }
+    }
+    Ok(())
+}
+
+/// A completed download, with the media type the server reported for it.
+pub struct Fetched {
+    pub data: Vec<u8>,
+    pub content_type: Option<String>,
+}
+
+/// Downloads `url` into `partial`, continuing from whatever a previous attempt left
+/// there when the file did not change since. The partial file is removed once the
+/// download completes.
+fn fetch_resumable(http: &Http, url: &str, partial: &Path, progress: bool) -> Result<Fetched> {
+    fs::create_dir_all(partial.parent().unwrap()).context(IOError {})?;
+
+    let mut response = loop {
+        // Without a validator, there is no telling whether the file changed since.
+        let saved_validator = fs::read_to_string(validator_path(partial)).ok();
+        if saved_validator.is_none() {
+            discard_partial(partial)?;
+        }
+        let resume_from = fs::metadata(partial).map(|meta| meta.len()).unwrap_or(0);
+        let build = || {
+            let request = http.request(Method::GET, url);
+            match saved_validator.as_ref() {
+                Some(validator) if resume_from > 0 => request
+                    .header(RANGE, format!("bytes={}-", resume_from))
+                    .header(IF_RANGE, validator.as_str()),
+                _ => request,
+            }
+        };
+        let response = http.send_idempotent(build).context(ExerciseDownload {})?;
+        // The partial file is useless if the server can no longer continue it.
+        let unsatisfiable = response.status() == StatusCode::RANGE_NOT_SATISFIABLE;
+        let misplaced = response.status() == StatusCode::PARTIAL_CONTENT
+            && range_start(&response) != Some(resume_from);
+        if resume_from > 0 && (unsatisfiable || misplaced) {
+            discard_partial(partial)?;
+            continue;
+        }
+        break response;
+    };
+
+    ensure!(
+        response.status().is_success(),
+        InvalidAPIResponse {
+            expected: StatusCode::OK,
+            received: response.status()
+        }
+    );
+
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    if !resumed {
+        match validator(&response) {
+            Some(validator) => fs::write(validator_path(partial), validator).context(IOError {})?,
+            None => discard_partial(partial)?,
+        }
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(partial)
+        .context(IOError {})?;
+    let mut file = BufWriter::new(file);
+    let already_downloaded = if resumed {
+        fs::metadata(partial).context(IOError {})?.len()
+    } else {
+        0
+    };
+
+    let total = response
+        .content_length()
+        .map(|length| length + already_downloaded);
+    let bar = progress_bar(total, progress);
+    bar.set_position(already_downloaded);
+
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = response.read(&mut buffer).context(DownloadInterrupted {})?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read]).context(IOError {})?;
+        bar.inc(read as u64);
+    }
+    file.flush().context(IOError {})?;
+    bar.finish_and_clear();
+
+    let data = fs::read(partial).context(IOError {})?;
+    discard_partial(partial)?;
+    Ok(Fetched { data, content_type })
+}
+
+/// Keeps resuming the download of `url` while the connection drops in the middle
+/// of it, within the retry policy of `http`.
+fn fetch_with_retries(http: &Http, url: &str, partial: &Path, progress: bool) -> Result<Fetched> {
+    let mut retry = 0;
+    loop {
+        match fetch_resumable(http, url, partial, progress) {
+            Err(Error::DownloadInterrupted { .. }) if retry < http.retry.max_retries => {
+                retry += 1;
+                eprintln!("The download was interrupted, resuming it");
+                thread::sleep(http.retry.backoff(retry));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Downloads `url`, resuming an earlier interrupted attempt when possible. A progress
+/// bar is shown when `progress` is set and the output is a terminal.
+pub fn fetch(http: &Http, url: &str, progress: bool) -> Result<Fetched> {
+    fetch_with_retries(http, url, &partial_path(url), progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::http::RetryPolicy;
+    use reqwest::Url;
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::time::Duration;
+
+    static ARCHIVE: &[u8] = b"pretend this is a gzipped tarball of a whole exercise";
+
+    fn read_request(stream: &TcpStream) -> String {
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" || line.is_empty() {
+                return request;
+            }
+            request.push_str(&line);
+        }
+    }
+
+    /// Answers each connection with the next of `responses`, then drops it.
+    /// Returns the URL and the requests received.
+    fn server(responses: Vec<String>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/exercise.tar.gz", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                sender.send(read_request(&stream)).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    /// A response for `ARCHIVE` from `start`, cut off at `end`.
+    fn response(headers: &str, start: usize, end: usize) -> String {
+        let status = if start > 0 {
+            format!(
+                "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                start,
+                ARCHIVE.len() - 1,
+                ARCHIVE.len()
+            )
+        } else {
+            "200 OK".to_owned()
+        };
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            ARCHIVE.len() - start,
+            String::from_utf8_lossy(&ARCHIVE[start..end])
+        )
+    }
+
+    /// Serves half of the archive and drops the connection, then honours the
+    /// range request that follows.
+    fn flaky_server() -> (String, Receiver<String>) {
+        let half = ARCHIVE.len() / 2;
+        server(vec![
+            response("ETag: \"v1\"\r\n", 0, half),
+            response("ETag: \"v1\"\r\n", half, ARCHIVE.len()),
+        ])
+    }
+
+    fn http() -> Http {
+        let api = Url::parse("http://localhost:5000/api/").unwrap();
+        Http::new(&Config::default(), api)
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_retries: 1,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(1),
+            })
+    }
+
+    #[test]
+    fn resumes_interrupted_download() {
+        let (url, requests) = flaky_server();
+        let cache = tempfile::tempdir().unwrap();
+        let partial = cache.path().join("partial");
+
+        let interrupted = fetch_resumable(&http(), &url, &partial, false);
+        assert!(interrupted.is_err());
+        assert_eq!(fs::read(&partial).unwrap(), &ARCHIVE[..ARCHIVE.len() / 2]);
+
+        let archive = fetch_resumable(&http(), &url, &partial, false).unwrap();
+        assert_eq!(archive.data, ARCHIVE);
+        assert!(!partial.exists());
+
+        let first = requests.recv().unwrap().to_lowercase();
+        assert!(!first.contains("range:"));
+        let second = requests.recv().unwrap().to_lowercase();
+        assert!(second.contains(&format!("range: bytes={}-", ARCHIVE.len() / 2)));
+        assert!(second.contains("if-range: \"v1\""));
+    }
+
+    #[test]
+    fn restarts_downloads_it_cannot_validate() {
+        let half = ARCHIVE.len() / 2;
+        let (url, requests) = server(vec![response("", 0, half), response("", 0, ARCHIVE.len())]);
+        let cache = tempfile::tempdir().unwrap();
+        let partial = cache.path().join("partial");
+
+        assert!(fetch_resumable(&http(), &url, &partial, false).is_err());
+        let archive = fetch_resumable(&http(), &url, &partial, false).unwrap();
+        assert_eq!(archive.data, ARCHIVE);
+        let second = requests.iter().nth(1).unwrap().to_lowercase();
+        assert!(!second.contains("range:"));
+    }
+
+    #[test]
+    fn restarts_when_the_server_resumes_elsewhere() {
+        let half = ARCHIVE.len() / 2;
+        let (url, requests) = server(vec![
+            response("ETag: \"v1\"\r\n", 0, half),
+            response("ETag: \"v1\"\r\n", 1, ARCHIVE.len()),
+            response("ETag: \"v1\"\r\n", 0, ARCHIVE.len()),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+        let partial = cache.path().join("partial");
+
+        assert!(fetch_resumable(&http(), &url, &partial, false).is_err());
+        let archive = fetch_resumable(&http(), &url, &partial, false).unwrap();
+        assert_eq!(archive.data, ARCHIVE);
+        assert!(!validator_path(&partial).exists());
+        let third = requests.iter().nth(2).unwrap().to_lowercase();
+        assert!(!third.contains("range:"));
+    }
+
+    #[test]
+    fn resumes_automatically_when_the_connection_drops() {
+        let (url, requests) = flaky_server();
+        let cache = tempfile::tempdir().unwrap();
+        let partial = cache.path().join("partial");
+
+        let archive = fetch_with_retries(&http(), &url, &partial, false).unwrap();
+        assert_eq!(archive.data, ARCHIVE);
+        assert_eq!(requests.iter().count(), 2);
+    }
+}
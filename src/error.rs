@@ -30,6 +30,12 @@ pub enum Error {
     #[snafu(display("An internal error occurred: {}", source))]
     ClientBuild { source: reqwest::Error },
 
+    #[snafu(display("Invalid Sharpener server URL {}: {}", url, source))]
+    InvalidServerUrl {
+        url: String,
+        source: reqwest::UrlError,
+    },
+
     #[snafu(display("Could not complete request to Sharpener server: {}", source))]
     ServerRequest { source: reqwest::Error },
 
@@ -46,12 +52,78 @@ pub enum Error {
         received: reqwest::StatusCode,
     },
 
+    #[snafu(display(
+        "Your CLI token has expired or was revoked ({}). Generate a new one on the Sharpener website and run `sharpener config <token>`",
+        message
+    ))]
+    ExpiredToken { message: String },
+
+    #[snafu(display(
+        "{}. Check the submission token, or run `sharpener list` to see your pending exercises",
+        message
+    ))]
+    ExerciseNotFound { message: String },
+
+    #[snafu(display(
+        "No solution was submitted for {} yet. Run `sharpener submit` in the exercise directory to send one",
+        token
+    ))]
+    NotSubmitted { token: String },
+
+    #[snafu(display(
+        "{}. This submission was already graded, run `sharpener list` to see your pending exercises",
+        message
+    ))]
+    SubmissionGraded { message: String },
+
+    #[snafu(display(
+        "The Sharpener server is receiving too many requests, try again {}",
+        retry_after.map_or_else(|| "later".to_owned(), |secs| format!("in {} seconds", secs))
+    ))]
+    RateLimited { retry_after: Option<u64> },
+
+    #[snafu(display(
+        "The Sharpener server refused the request ({}): {}{}",
+        status,
+        message,
+        field_errors
+    ))]
+    ServerRejected {
+        status: reqwest::StatusCode,
+        message: String,
+        field_errors: String,
+    },
+
     #[snafu(display("Unable to download exercise: {}", source))]
     ExerciseDownload { source: reqwest::Error },
 
+    #[snafu(display("Download interrupted, run the command again to resume it: {}", source))]
+    DownloadInterrupted { source: std::io::Error },
+
+    #[snafu(display("Unable to download exercise: {}", reason))]
+    InvalidDownload { reason: String },
+
+    #[snafu(display("{} of {} exercises could not be downloaded", failed, total))]
+    BulkDownload { failed: usize, total: usize },
+
+    #[snafu(display("Refusing to unpack exercise entry {}: {}", path.display(), reason))]
+    UnsafeArchive { path: PathBuf, reason: String },
+
     #[snafu(display("Unable to download exercise: {}", source))]
     UnpackTar { source: std::io::Error },
 
+    #[snafu(display("Unable to download exercise: {}", source))]
+    UnpackZip { source: zip::result::ZipError },
+
+    #[snafu(display("{} already exists. Run again with --force to replace it, --backup to move it aside or --update-tests-only to only refresh its tests.", path.display()))]
+    ExerciseExists { path: PathBuf },
+
+    #[snafu(display("{} contains a different submission, refusing to {}.", path.display(), action))]
+    ForeignExercise { path: PathBuf, action: &'static str },
+
+    #[snafu(display("Unable to write archive cache entry: {}", source))]
+    WriteCacheEntry { source: serde_json::error::Error },
+
     #[snafu(display("Unable to load exercise metadata: {}", source))]
     OpenMetaFile { source: std::io::Error },
 
@@ -67,9 +139,30 @@ pub enum Error {
     #[snafu(display("Exercise metadata not found. Make sure you're inside a directory created by `sharpener download`."))]
     MissingMeta,
 
+    #[snafu(display("No test file checksums were recorded for this exercise. Download it again to enable verification."))]
+    MissingManifest,
+
+    #[snafu(display("Unsupported checksum algorithm \"{}\". You may need to update your sharpener CLI before proceeding.", algorithm))]
+    UnsupportedChecksum { algorithm: String },
+
     #[snafu(display("Unable to run test command: {}", source))]
     TestCommand { source: std::io::Error },
 
+    #[snafu(display(
+        "The tests did not pass{}",
+        exit_code.map_or_else(String::new, |code| format!(", the test runner exited with code {}", code))
+    ))]
+    TestsFailed { exit_code: Option<i32> },
+
+    #[snafu(display("Watching for changes does not support JSON output"))]
+    WatchJsonOutput,
+
+    #[snafu(display("--format {} cannot be combined with --output json", format))]
+    ListFormatJsonOutput { format: String },
+
+    #[snafu(display("Unable to watch exercise files: {}", source))]
+    WatchFiles { source: notify::Error },
+
     #[snafu(display("Test output contains invalid characters: {}", source))]
     InvalidTestOutput { source: std::string::FromUtf8Error },
 
@@ -78,6 +171,18 @@ pub enum Error {
     ))]
     InvalidForfeit,
 
+    #[snafu(display("The test files differ from the ones that were downloaded:\n{}", diff))]
+    ModifiedTests { diff: String },
+
+    #[snafu(display("Submission cancelled: {}", reason))]
+    SubmissionRefused { reason: String },
+
+    #[snafu(display(
+        "The solution is still being graded after {} seconds, run `sharpener status` to check on it later",
+        waited_secs
+    ))]
+    GradingTimeout { waited_secs: u64 },
+
     #[snafu(display("Unable to open file required for submission at {}: {}", filename.display(), source))]
     OpenSubmissionFile {
         filename: PathBuf,
@@ -91,4 +196,213 @@ pub enum Error {
     },
 }
 
+impl Error {
+    /// Identifies the error in JSON output. These names are part of the output
+    /// schema, so they must not change.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::ConfigWrite { .. } => "config_write",
+            Self::OpenConfigFile { .. } => "open_config_file",
+            Self::ConfigParsing { .. } => "config_parsing",
+            Self::InvalidToken { .. } => "invalid_token",
+            Self::ClientBuild { .. } => "client_build",
+            Self::InvalidServerUrl { .. } => "invalid_server_url",
+            Self::ServerRequest { .. } => "server_request",
+            Self::ParseSubmissionResponse { .. } => "parse_submission_response",
+            Self::InvalidAPIResponse { .. } => "invalid_api_response",
+            Self::ExpiredToken { .. } => "expired_token",
+            Self::ExerciseNotFound { .. } => "exercise_not_found",
+            Self::NotSubmitted { .. } => "not_submitted",
+            Self::SubmissionGraded { .. } => "submission_graded",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::ServerRejected { .. } => "server_rejected",
+            Self::ExerciseDownload { .. } => "exercise_download",
+            Self::DownloadInterrupted { .. } => "download_interrupted",
+            Self::InvalidDownload { .. } => "invalid_download",
+            Self::BulkDownload { .. } => "bulk_download",
+            Self::UnsafeArchive { .. } => "unsafe_archive",
+            Self::UnpackTar { .. } => "unpack_tar",
+            Self::UnpackZip { .. } => "unpack_zip",
+            Self::ExerciseExists { .. } => "exercise_exists",
+            Self::ForeignExercise { .. } => "foreign_exercise",
+            Self::WriteCacheEntry { .. } => "write_cache_entry",
+            Self::OpenMetaFile { .. } => "open_meta_file",
+            Self::ParseMetaFile { .. } => "parse_meta_file",
+            Self::WriteMetaFile { .. } => "write_meta_file",
+            Self::IOError { .. } => "io_error",
+            Self::MissingMeta => "missing_meta",
+            Self::MissingManifest => "missing_manifest",
+            Self::UnsupportedChecksum { .. } => "unsupported_checksum",
+            Self::TestCommand { .. } => "test_command",
+            Self::TestsFailed { .. } => "tests_failed",
+            Self::WatchJsonOutput => "watch_json_output",
+            Self::ListFormatJsonOutput { .. } => "list_format_json_output",
+            Self::WatchFiles { .. } => "watch_files",
+            Self::InvalidTestOutput { .. } => "invalid_test_output",
+            Self::InvalidForfeit => "invalid_forfeit",
+            Self::ModifiedTests { .. } => "modified_tests",
+            Self::SubmissionRefused { .. } => "submission_refused",
+            Self::GradingTimeout { .. } => "grading_timeout",
+            Self::OpenSubmissionFile { .. } => "open_submission_file",
+            Self::ReadSubmissionFile { .. } => "read_submission_file",
+        }
+    }
+
+    /// The exit code the CLI ends with after this error, see `exit_code`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::TestsFailed { .. } | Self::SubmissionRefused { .. } => exit_code::TESTS_FAILED,
+            Self::MissingMeta
+            | Self::OpenMetaFile { .. }
+            | Self::ParseMetaFile { .. }
+            | Self::MissingManifest => exit_code::NOT_IN_EXERCISE,
+            Self::OpenConfigFile { .. }
+            | Self::ConfigParsing { .. }
+            | Self::InvalidServerUrl { .. } => exit_code::CONFIG,
+            Self::InvalidToken { .. } | Self::ExpiredToken { .. } => exit_code::INVALID_TOKEN,
+            Self::ServerRequest { .. }
+            | Self::ExerciseDownload { .. }
+            | Self::DownloadInterrupted { .. } => exit_code::SERVER_UNREACHABLE,
+            Self::ParseSubmissionResponse { .. }
+            | Self::InvalidAPIResponse { .. }
+            | Self::ExerciseNotFound { .. }
+            | Self::NotSubmitted { .. }
+            | Self::SubmissionGraded { .. }
+            | Self::RateLimited { .. }
+            | Self::ServerRejected { .. }
+            | Self::InvalidForfeit => exit_code::SERVER_REFUSED,
+            Self::ExerciseExists { .. }
+            | Self::ForeignExercise { .. }
+            | Self::ModifiedTests { .. } => exit_code::EXERCISE_CONFLICT,
+            Self::InvalidDownload { .. }
+            | Self::BulkDownload { .. }
+            | Self::UnsafeArchive { .. }
+            | Self::UnpackTar { .. }
+            | Self::UnpackZip { .. }
+            | Self::UnsupportedChecksum { .. } => exit_code::DOWNLOAD_FAILED,
+            Self::GradingTimeout { .. } => exit_code::GRADING_PENDING,
+            Self::WatchJsonOutput | Self::ListFormatJsonOutput { .. } => exit_code::USAGE,
+            Self::ConfigWrite { .. }
+            | Self::ClientBuild { .. }
+            | Self::WriteCacheEntry { .. }
+            | Self::WriteMetaFile { .. }
+            | Self::IOError { .. }
+            | Self::TestCommand { .. }
+            | Self::WatchFiles { .. }
+            | Self::InvalidTestOutput { .. }
+            | Self::OpenSubmissionFile { .. }
+            | Self::ReadSubmissionFile { .. } => exit_code::FAILURE,
+        }
+    }
+}
+
+/// Exit codes of the CLI, as listed in `sharpener --help`. Scripts rely on them,
+/// so a code never changes meaning once released.
+pub mod exit_code {
+    /// Failures without a more specific code.
+    pub const FAILURE: i32 = 1;
+    pub const USAGE: i32 = 2;
+    pub const TESTS_FAILED: i32 = 3;
+    pub const NOT_IN_EXERCISE: i32 = 4;
+    pub const CONFIG: i32 = 5;
+    pub const INVALID_TOKEN: i32 = 6;
+    pub const SERVER_UNREACHABLE: i32 = 7;
+    pub const SERVER_REFUSED: i32 = 8;
+    pub const EXERCISE_CONFLICT: i32 = 9;
+    pub const DOWNLOAD_FAILED: i32 = 10;
+    pub const GRADING_PENDING: i32 = 11;
+
+    pub const HELP: &str = "EXIT CODES:
+    0     Success
+    1     Unexpected failure
+    2     Invalid command line arguments
+    3     The tests did not pass, or a submission was refused because of them
+    4     Not inside an exercise directory, or its metadata is unreadable
+    5     Missing or invalid configuration, run `sharpener config`
+    6     The CLI token is invalid, expired or revoked
+    7     The Sharpener server could not be reached
+    8     The Sharpener server refused the request
+    9     The exercise files conflict with the command, e.g. modified tests
+    10    The exercise could not be downloaded or unpacked
+    11    The solution was not graded before `submit --wait` gave up";
+}
+
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_tell_error_categories_apart() {
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let cases = vec![
+            (Error::MissingMeta, exit_code::NOT_IN_EXERCISE),
+            (
+                Error::OpenConfigFile {
+                    filename: PathBuf::from("/home/user/.sharpener-config"),
+                    source: not_found,
+                },
+                exit_code::CONFIG,
+            ),
+            (
+                Error::ExpiredToken {
+                    message: "Token expired".to_owned(),
+                },
+                exit_code::INVALID_TOKEN,
+            ),
+            (
+                Error::RateLimited { retry_after: None },
+                exit_code::SERVER_REFUSED,
+            ),
+            (
+                Error::TestsFailed {
+                    exit_code: Some(101),
+                },
+                exit_code::TESTS_FAILED,
+            ),
+            (
+                Error::ModifiedTests {
+                    diff: "modified: tests/tests.rs".to_owned(),
+                },
+                exit_code::EXERCISE_CONFLICT,
+            ),
+            (
+                Error::BulkDownload {
+                    failed: 1,
+                    total: 2,
+                },
+                exit_code::DOWNLOAD_FAILED,
+            ),
+            (Error::WatchJsonOutput, exit_code::USAGE),
+            (
+                Error::ListFormatJsonOutput {
+                    format: "csv".to_owned(),
+                },
+                exit_code::USAGE,
+            ),
+            (Error::InvalidForfeit, exit_code::SERVER_REFUSED),
+            (
+                Error::NotSubmitted {
+                    token: "token-1".to_owned(),
+                },
+                exit_code::SERVER_REFUSED,
+            ),
+            (
+                Error::GradingTimeout { waited_secs: 600 },
+                exit_code::GRADING_PENDING,
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.exit_code(), expected, "{}", error.kind());
+        }
+    }
+
+    #[test]
+    fn help_lists_every_exit_code() {
+        for code in 0..=exit_code::GRADING_PENDING {
+            let line = format!("\n    {:<6}", code);
+            assert!(exit_code::HELP.contains(&line), "{}", code);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::*;
+use crate::language::TestResults;
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Passing,
+    Failing(String),
+    Broken(String),
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Passing => "passing",
+            Self::Failing(_) => "failing",
+            Self::Broken(_) => "broken",
+        }
+    }
+}
+
+pub fn evaluate(results: Option<TestResults>, exit_code: Option<i32>) -> Verdict {
+    match results {
+        None => Verdict::Broken(
+            "No test results were found, the solution probably does not compile".to_owned(),
+        ),
+        Some(results) if results.failed > 0 => Verdict::Failing(format!(
+            "{} of {} tests failed",
+            results.failed,
+            results.total()
+        )),
+        Some(_) if exit_code != Some(0) => Verdict::Failing(match exit_code {
+            Some(code) => format!("The test runner exited with code {}", code),
+            None => "The test runner was terminated by a signal".to_owned(),
+        }),
+        Some(_) => Verdict::Passing,
+    }
+}
+
+/// Decides whether a submission should go ahead. `confirm` is only asked about
+/// failing solutions; broken ones are refused unless `force` is set.
+pub fn allow_submission<F>(
+    verdict: &Verdict,
+    force: bool,
+    assume_yes: bool,
+    confirm: F,
+) -> Result<()>
+where
+    F: FnOnce(&str) -> Result<bool>,
+{
+    match verdict {
+        _ if force => Ok(()),
+        Verdict::Passing => Ok(()),
+        Verdict::Broken(reason) => Err(Error::SubmissionRefused {
+            reason: format!("{}. Use --force to submit it anyway", reason),
+        }),
+        Verdict::Failing(_) if assume_yes => Ok(()),
+        Verdict::Failing(reason) => {
+            ensure!(
+                confirm(reason)?,
+                SubmissionRefused {
+                    reason: "cancelled by the user"
+                }
+            );
+            Ok(())
+        }
+    }
+}
+
+pub fn confirm_on_terminal(reason: &str) -> Result<bool> {
+    print!(
+        "{}. Submitting uses up one of your attempts, continue anyway? [y/N] ",
+        reason
+    );
+    io::stdout().flush().context(IOError {})?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context(IOError {})?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Stands in for `confirm_on_terminal` when nobody is there to answer.
+pub fn refuse_without_prompt(reason: &str) -> Result<bool> {
+    SubmissionRefused {
+        reason: format!("{}. Use --yes to submit it anyway", reason),
+    }
+    .fail()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::Language;
+
+    static CARGO_PASSING: &str = "
+running 2 tests
+test adds_two_numbers ... ok
+test adds_negative_numbers ... ok
+
+test result: ok. 2 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out
+";
+
+    static CARGO_FAILING: &str = "
+running 2 tests
+test adds_two_numbers ... ok
+test adds_negative_numbers ... FAILED
+
+test result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out
+";
+
+    static CARGO_NOT_COMPILING: &str = "
+error[E0308]: mismatched types
+ --> src/lib.rs:2:5
+error: aborting due to previous error
+";
+
+    static PYTEST_FAILING: &str = "
+tests/tests.py .F.                                                       [100%]
+========================= 1 failed, 2 passed in 0.05s =========================
+";
+
+    fn verdict(language: Language, output: &str, exit_code: i32) -> Verdict {
+        evaluate(language.parse_test_results(output), Some(exit_code))
+    }
+
+    #[test]
+    fn passing_output_is_submitted() {
+        let verdict = verdict(Language::Rust, CARGO_PASSING, 0);
+        assert_eq!(verdict, Verdict::Passing);
+        assert!(allow_submission(&verdict, false, false, |_| panic!("no prompt expected")).is_ok());
+    }
+
+    #[test]
+    fn failing_output_asks_for_confirmation() {
+        let verdict = verdict(Language::Rust, CARGO_FAILING, 101);
+        assert_eq!(verdict, Verdict::Failing("1 of 2 tests failed".to_owned()));
+        assert!(allow_submission(&verdict, false, false, |_| Ok(true)).is_ok());
+        assert!(allow_submission(&verdict, false, false, |_| Ok(false)).is_err());
+    }
+
+    #[test]
+    fn failing_python_output_asks_for_confirmation() {
+        let verdict = verdict(Language::Python, PYTEST_FAILING, 1);
+        assert_eq!(verdict, Verdict::Failing("1 of 3 tests failed".to_owned()));
+    }
+
+    #[test]
+    fn yes_skips_confirmation() {
+        let verdict = verdict(Language::Rust, CARGO_FAILING, 101);
+        assert!(allow_submission(&verdict, false, true, |_| panic!("no prompt expected")).is_ok());
+    }
+
+    #[test]
+    fn non_compiling_output_is_refused() {
+        let verdict = verdict(Language::Rust, CARGO_NOT_COMPILING, 101);
+        assert!(matches!(verdict, Verdict::Broken(_)));
+        assert!(allow_submission(&verdict, false, true, |_| Ok(true)).is_err());
+    }
+
+    #[test]
+    fn force_skips_the_gate() {
+        let verdict = verdict(Language::Rust, CARGO_NOT_COMPILING, 101);
+        assert!(allow_submission(&verdict, true, false, |_| panic!("no prompt expected")).is_ok());
+    }
+
+    #[test]
+    fn non_zero_exit_with_passing_tests_is_failing() {
+        let verdict = verdict(Language::Rust, CARGO_PASSING, 101);
+        assert!(matches!(verdict, Verdict::Failing(_)));
+    }
+}
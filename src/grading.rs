@@ -0,0 +1,213 @@
+use crate::api::ApiClient;
+use crate::error::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long `submit --wait` waits for grading by default, in seconds.
+pub const DEFAULT_WAIT_SECS: u64 = 600;
+
+/// Delay between two checks of a submission being graded.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GradingState {
+    Queued,
+    Grading,
+    Graded,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReviewComment {
+    pub reviewer: String,
+    pub message: String,
+}
+
+/// The latest state of a submitted solution, as the server reports it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Grading {
+    pub submission_id: String,
+    pub state: GradingState,
+
+    /// Set once graded, e.g. "passed" or "failed".
+    #[serde(default)]
+    pub verdict: Option<String>,
+
+    #[serde(default)]
+    pub score: Option<f64>,
+
+    #[serde(default)]
+    pub max_score: Option<f64>,
+
+    #[serde(default)]
+    pub comments: Vec<ReviewComment>,
+}
+
+impl Grading {
+    pub fn is_done(&self) -> bool {
+        self.state == GradingState::Graded
+    }
+}
+
+impl fmt::Display for Grading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            GradingState::Queued => "waiting to be graded",
+            GradingState::Grading => "being graded",
+            GradingState::Graded => "graded",
+        };
+        write!(f, "Submission {} is {}", self.submission_id, state)?;
+        if let Some(verdict) = &self.verdict {
+            write!(f, "\nVerdict: {}", verdict)?;
+        }
+        match (self.score, self.max_score) {
+            (Some(score), Some(max_score)) => write!(f, "\nScore: {}/{}", score, max_score)?,
+            (Some(score), None) => write!(f, "\nScore: {}", score)?,
+            _ => (),
+        }
+        if !self.comments.is_empty() {
+            write!(f, "\nReviewer comments:")?;
+            for comment in &self.comments {
+                write!(f, "\n  {}: {}", comment.reviewer, comment.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks on the submission `token` every `interval` until it is graded, calling
+/// `on_change` whenever its state changes. Gives up after `timeout`, though it
+/// always checks at least once, leaving the last known state in `grading`.
+pub fn wait<F>(
+    api: &dyn ApiClient,
+    token: &str,
+    grading: &mut Grading,
+    interval: Duration,
+    timeout: Duration,
+    mut on_change: F,
+) -> Result<()>
+where
+    F: FnMut(&Grading),
+{
+    let start = Instant::now();
+    let mut checked = false;
+    while !grading.is_done() {
+        let remaining = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+        ensure!(
+            !checked || remaining > Duration::from_secs(0),
+            GradingTimeout {
+                waited_secs: start.elapsed().as_secs()
+            }
+        );
+        thread::sleep(interval.min(remaining));
+        let latest = api.grading(token)?;
+        if latest.state != grading.state {
+            on_change(&latest);
+        }
+        *grading = latest;
+        checked = true;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fake::FakeApi;
+
+    fn grading(state: GradingState) -> Grading {
+        Grading {
+            submission_id: "submission-1".to_owned(),
+            state,
+            verdict: None,
+            score: None,
+            max_score: None,
+            comments: Vec::new(),
+        }
+    }
+
+    fn graded() -> Grading {
+        Grading {
+            verdict: Some("passed".to_owned()),
+            score: Some(9.5),
+            max_score: Some(10.0),
+            comments: vec![ReviewComment {
+                reviewer: "Ada".to_owned(),
+                message: "Nice and tidy".to_owned(),
+            }],
+            ..grading(GradingState::Graded)
+        }
+    }
+
+    #[test]
+    fn shows_verdict_score_and_comments() {
+        assert_eq!(
+            graded().to_string(),
+            "Submission submission-1 is graded\nVerdict: passed\nScore: 9.5/10\nReviewer comments:\n  Ada: Nice and tidy"
+        );
+        assert_eq!(
+            grading(GradingState::Queued).to_string(),
+            "Submission submission-1 is waiting to be graded"
+        );
+    }
+
+    #[test]
+    fn waits_until_graded() {
+        let api = FakeApi::default().with_gradings(
+            "token-1",
+            vec![
+                grading(GradingState::Grading),
+                grading(GradingState::Grading),
+                graded(),
+            ],
+        );
+        let mut changes = Vec::new();
+        let mut latest = grading(GradingState::Queued);
+        wait(
+            &api,
+            "token-1",
+            &mut latest,
+            Duration::from_millis(1),
+            Duration::from_secs(5),
+            |grading| changes.push(grading.state),
+        )
+        .unwrap();
+        assert_eq!(latest.verdict.as_deref(), Some("passed"));
+        assert_eq!(changes, vec![GradingState::Grading, GradingState::Graded]);
+    }
+
+    #[test]
+    fn checks_once_within_short_timeouts() {
+        let api = FakeApi::default().with_gradings("token-1", vec![graded()]);
+        let mut latest = grading(GradingState::Queued);
+        let interval = Duration::from_secs(60);
+        wait(
+            &api,
+            "token-1",
+            &mut latest,
+            interval,
+            Duration::from_millis(10),
+            |_| (),
+        )
+        .unwrap();
+        assert!(latest.is_done());
+    }
+
+    #[test]
+    fn gives_up_after_the_timeout() {
+        let api = FakeApi::default().with_gradings("token-1", vec![grading(GradingState::Grading)]);
+        let mut latest = grading(GradingState::Queued);
+        let result = wait(
+            &api,
+            "token-1",
+            &mut latest,
+            Duration::from_millis(20),
+            Duration::from_millis(50),
+            |_| (),
+        );
+        assert!(matches!(result, Err(Error::GradingTimeout { .. })));
+        assert_eq!(latest.state, GradingState::Grading);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::config::Config;
+use crate::error::*;
+use reqwest::header::{HeaderValue, AUTHORIZATION, RETRY_AFTER};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+pub const DEFAULT_READ_TIMEOUT_SECS: u64 = 30;
+
+/// How idempotent requests are retried after transient failures.
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry`, doubling every time.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay
+            .checked_mul(1 << retry.saturating_sub(1).min(16))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// The delay asked for by a `Retry-After` header, when given in seconds.
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// The HTTP client shared by every request, to the server and for downloads.
+pub struct Http {
+    client: Client,
+    token: HeaderValue,
+    api: Url,
+    pub retry: RetryPolicy,
+}
+
+impl Http {
+    pub fn new(config: &Config, api: Url) -> Result<Self> {
+        let token = HeaderValue::from_str(&config.token).context(InvalidToken {})?;
+        let connect_timeout = config
+            .connect_timeout_secs
+            .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
+        let read_timeout = config
+            .read_timeout_secs
+            .unwrap_or(DEFAULT_READ_TIMEOUT_SECS);
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(connect_timeout))
+            .timeout(Duration::from_secs(read_timeout))
+            .build()
+            .context(ClientBuild {})?;
+        Ok(Self {
+            client,
+            token,
+            api,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Starts a request to `url`. Only requests to the Sharpener server carry the
+    /// user's token, so it never leaks to where archives are stored.
+    pub fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        let to_api = Url::parse(url).map(|url| url.origin()) == Ok(self.api.origin());
+        if to_api {
+            request.header(AUTHORIZATION, self.token.clone())
+        } else {
+            request
+        }
+    }
+
+    /// Starts a request to `path` under the API root.
+    pub fn api_request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = self.api.join(path).unwrap();
+        self.request(method, url.as_str())
+    }
+
+    /// Sends the request made by `build`, retrying with exponential backoff when
+    /// the connection fails or the server answers with a 5xx or 429. A delay asked
+    /// for with `Retry-After` is honoured. Only use it for idempotent requests.
+    pub fn send_idempotent<F>(&self, build: F) -> reqwest::Result<Response>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut retry = 0;
+        loop {
+            let result = build().send();
+            let delay = match &result {
+                Ok(response) if is_transient(response.status()) => {
+                    retry_after(response).unwrap_or_else(|| self.retry.backoff(retry + 1))
+                }
+                Err(error) if error.is_http() || error.is_timeout() => {
+                    self.retry.backoff(retry + 1)
+                }
+                _ => return result,
+            };
+            if retry >= self.retry.max_retries {
+                return result;
+            }
+            retry += 1;
+            thread::sleep(delay.min(self.retry.max_delay));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::time::Instant;
+
+    /// Answers one connection with each of `responses` in turn, and returns the
+    /// server's base URL and the requests it received.
+    fn scripted_server(responses: Vec<&'static str>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                sender.send(request).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    response
+                )
+                .unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn http(api: &str) -> Http {
+        let config = Config {
+            token: "user-token".to_owned(),
+            ..Config::default()
+        };
+        Http::new(&config, Url::parse(api).unwrap())
+            .unwrap()
+            .with_retry_policy(RetryPolicy {
+                max_retries: 2,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_secs(5),
+            })
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(40), policy.max_delay);
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let (url, requests) = scripted_server(vec!["503 Service Unavailable", "200 OK"]);
+        let http = http(&url);
+        let response = http
+            .send_idempotent(|| http.request(Method::GET, &url))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(requests.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_retry() {
+        let (url, requests) = scripted_server(vec!["502 Bad Gateway"; 3]);
+        let http = http(&url);
+        let response = http
+            .send_idempotent(|| http.request(Method::GET, &url))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(requests.try_iter().count(), 3);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let (url, requests) = scripted_server(vec!["404 Not Found"]);
+        let http = http(&url);
+        let response = http
+            .send_idempotent(|| http.request(Method::GET, &url))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(requests.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        let (url, _requests) =
+            scripted_server(vec!["429 Too Many Requests\r\nRetry-After: 1", "200 OK"]);
+        let http = http(&url);
+        let start = Instant::now();
+        let response = http
+            .send_idempotent(|| http.request(Method::GET, &url))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sends_token_to_the_server_only() {
+        let (api, api_requests) = scripted_server(vec!["200 OK"]);
+        let (storage, storage_requests) = scripted_server(vec!["200 OK"]);
+        let http = http(&api);
+
+        http.api_request(Method::GET, "submissions").send().unwrap();
+        http.request(Method::GET, &storage).send().unwrap();
+        let api_request = api_requests.recv().unwrap().to_lowercase();
+        assert!(api_request.contains("authorization: user-token"));
+        let storage_request = storage_requests.recv().unwrap().to_lowercase();
+        assert!(!storage_request.contains("authorization"));
+    }
+}
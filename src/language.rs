@@ -16,7 +16,27 @@ macro_rules! unimplemented_language {
     };
 }
 
-fn parse_rust_coverage(command_output: &str) -> String {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TestResults {
+    pub passed: u32,
+    pub failed: u32,
+}
+
+impl TestResults {
+    fn from_counts(passed: u32, failed: u32) -> Option<Self> {
+        if passed == 0 && failed == 0 {
+            None
+        } else {
+            Some(Self { passed, failed })
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.passed + self.failed
+    }
+}
+
+fn parse_rust_results(command_output: &str) -> Option<TestResults> {
     let regex =
         Regex::new(r"test result: (?:ok|FAILED)\. ([0-9]+) passed; ([0-9]+) failed;").unwrap();
     let (passed, failed) = regex
@@ -26,13 +46,53 @@ fn parse_rust_coverage(command_output: &str) -> String {
             let failed: u32 = captures.get(2).unwrap().as_str().parse().unwrap();
             (acc.0 + passed, acc.1 + failed)
         });
-    if passed == 0 && failed == 0 {
-        "No test results".to_owned()
-    } else {
-        format!("{}/{}", passed, passed + failed)
+    TestResults::from_counts(passed, failed)
+}
+
+fn parse_python_results(command_output: &str) -> Option<TestResults> {
+    let summary = Regex::new(r"=+ (.*) in [0-9.]+s.* =+").unwrap();
+    let count = Regex::new(r"([0-9]+) (passed|failed|error)").unwrap();
+    let (passed, failed) = summary
+        .captures_iter(command_output)
+        .flat_map(|captures| {
+            let line = captures.get(1).unwrap().as_str();
+            count
+                .captures_iter(line)
+                .map(|captures| {
+                    let amount: u32 = captures.get(1).unwrap().as_str().parse().unwrap();
+                    match captures.get(2).unwrap().as_str() {
+                        "passed" => (amount, 0),
+                        _ => (0, amount),
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .fold((0, 0), |acc, (passed, failed)| {
+            (acc.0 + passed, acc.1 + failed)
+        });
+    TestResults::from_counts(passed, failed)
+}
+
+fn split_harness_args(args: &[String]) -> (&[String], &[String]) {
+    match args.iter().position(|arg| arg == "--") {
+        Some(index) => (&args[..index], &args[index + 1..]),
+        None => (args, &[]),
     }
 }
 
+/// Joins runner arguments from several sources, keeping every argument meant for
+/// the test harness (after `--`) behind a single separator.
+pub fn merge_runner_args(saved: &[String], extra: &[String]) -> Vec<String> {
+    let (saved_runner, saved_harness) = split_harness_args(saved);
+    let (extra_runner, extra_harness) = split_harness_args(extra);
+    let mut args: Vec<String> = saved_runner.iter().chain(extra_runner).cloned().collect();
+    if !saved_harness.is_empty() || !extra_harness.is_empty() {
+        args.push("--".to_owned());
+        args.extend(saved_harness.iter().chain(extra_harness).cloned());
+    }
+    args
+}
+
 impl Language {
     pub fn as_str(&self) -> &str {
         match self {
@@ -54,13 +114,83 @@ impl Language {
         }
     }
 
-    pub fn parse_test_coverage(&self, command_output: &str) -> String {
+    /// Builds the test command for a local run, narrowed down to the tests matching
+    /// `filter` and extended with `runner_args`. Submissions must keep using
+    /// `test_command` so that graded results come from the canonical invocation.
+    pub fn custom_test_command(
+        &self,
+        filter: Option<&str>,
+        exact: bool,
+        runner_args: &[String],
+    ) -> Command {
+        let mut command = self.test_command();
         match self {
-            Self::Rust => parse_rust_coverage(command_output),
+            Self::Python => {
+                match filter {
+                    Some(filter) if exact => {
+                        let test_id = format!("{}::{}", self.test_file_path().display(), filter);
+                        command.arg(test_id);
+                    }
+                    Some(filter) => {
+                        command.args(["-k", filter]);
+                    }
+                    None => (),
+                }
+                command.args(runner_args);
+            }
+            Self::Rust => {
+                // Arguments after `--` belong to the test harness, which is also where
+                // the filter goes, so cargo arguments have to be kept before it.
+                let (cargo_args, harness_args) = split_harness_args(runner_args);
+                command.args(cargo_args);
+                command.arg("--");
+                if let Some(filter) = filter {
+                    command.arg(filter);
+                    if exact {
+                        command.arg("--exact");
+                    }
+                }
+                command.args(harness_args);
+            }
+            Self::Other(s) => unimplemented_language!(s),
+        }
+        command
+    }
+
+    pub fn list_tests_command(&self) -> Command {
+        let mut command = self.test_command();
+        match self {
+            Self::Python => command.args(["--collect-only", "-q"]),
+            Self::Rust => command.args(["--", "--list"]),
+            Self::Other(s) => unimplemented_language!(s),
+        };
+        command
+    }
+
+    pub fn parse_test_results(&self, command_output: &str) -> Option<TestResults> {
+        match self {
+            Self::Rust => parse_rust_results(command_output),
+            Self::Python => parse_python_results(command_output),
             _ => unimplemented_language!(self.as_str()),
         }
     }
 
+    pub fn parse_test_coverage(&self, command_output: &str) -> String {
+        match self.parse_test_results(command_output) {
+            Some(results) => format!("{}/{}", results.passed, results.total()),
+            None => "No test results".to_owned(),
+        }
+    }
+
+    /// Where the tests of an exercise live, unknown for languages that this
+    /// version of sharpener does not support yet.
+    pub fn test_directory(&self) -> Option<&Path> {
+        match self {
+            Self::Rust | Self::Python => Some(Path::new("tests")),
+            _ => None,
+        }
+    }
+
     pub fn test_file_path(&self) -> &Path {
         match self {
             Self::Rust => Path::new("tests/tests.rs"),
@@ -69,6 +199,22 @@ impl Language {
         }
     }
 
+    pub fn build_directories(&self) -> &[&str] {
+        match self {
+            Self::Rust => &["target"],
+            Self::Python => &["__pycache__", ".pytest_cache"],
+            _ => &[],
+        }
+    }
+
+    /// Files the test runner writes into the exercise directory on its own.
+    pub fn generated_files(&self) -> &[&str] {
+        match self {
+            Self::Rust => &["Cargo.lock"],
+            _ => &[],
+        }
+    }
+
     pub fn solution_file_path(&self) -> &Path {
         match self {
             Self::Rust => Path::new("src/lib.rs"),
@@ -107,3 +253,125 @@ impl<'de> Deserialize<'de> for Language {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(command_output: &str) -> Option<(u32, u32)> {
+        parse_python_results(command_output).map(|results| (results.passed, results.failed))
+    }
+
+    #[test]
+    fn parses_passing_pytest_runs() {
+        let output =
+            "tests/test_solution.py ...                                               [100%]\n\
+            \n\
+            ============================== 3 passed in 0.12s ===============================\n";
+        assert_eq!(counts(output), Some((3, 0)));
+    }
+
+    #[test]
+    fn parses_failing_pytest_runs() {
+        let output =
+            "=========================== short test summary info ============================\n\
+            FAILED tests/test_solution.py::test_empty - AssertionError\n\
+            ========================= 1 failed, 2 passed in 0.34s ==========================\n";
+        assert_eq!(counts(output), Some((2, 1)));
+    }
+
+    #[test]
+    fn counts_pytest_errors_as_failures() {
+        let output =
+            "ERROR tests/test_solution.py - ModuleNotFoundError: No module named 'solution'\n\
+            ==================== 1 failed, 1 passed, 2 errors in 0.56s =====================\n";
+        assert_eq!(counts(output), Some((1, 3)));
+        let output =
+            "=============================== 1 error in 0.08s ===============================\n";
+        assert_eq!(counts(output), Some((0, 1)));
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn argv(command: &Command) -> Vec<String> {
+        Some(command.get_program())
+            .into_iter()
+            .chain(command.get_args())
+            .map(|word| word.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn filters_python_tests() {
+        let python = Language::Python;
+        let run = |filter, exact| argv(&python.custom_test_command(filter, exact, &args(&["-x"])));
+        assert_eq!(run(None, false), args(&["pytest", "-x"]));
+        assert_eq!(
+            run(Some("answer"), false),
+            args(&["pytest", "-k", "answer", "-x"])
+        );
+        assert_eq!(
+            run(Some("test_answer"), true),
+            args(&["pytest", "tests/tests.py::test_answer", "-x"])
+        );
+        assert_eq!(
+            argv(&python.list_tests_command()),
+            args(&["pytest", "--collect-only", "-q"])
+        );
+    }
+
+    #[test]
+    fn filters_rust_tests() {
+        let rust = Language::Rust;
+        let runner_args = args(&["--release", "--", "--nocapture"]);
+        let run = |filter, exact| argv(&rust.custom_test_command(filter, exact, &runner_args));
+        assert_eq!(
+            run(None, false),
+            args(&["cargo", "test", "--release", "--", "--nocapture"])
+        );
+        assert_eq!(
+            run(Some("answer"), false),
+            args(&["cargo", "test", "--release", "--", "answer", "--nocapture"])
+        );
+        assert_eq!(
+            run(Some("answer"), true),
+            args(&[
+                "cargo",
+                "test",
+                "--release",
+                "--",
+                "answer",
+                "--exact",
+                "--nocapture"
+            ])
+        );
+        assert_eq!(
+            argv(&rust.list_tests_command()),
+            args(&["cargo", "test", "--", "--list"])
+        );
+    }
+
+    #[test]
+    fn merges_runner_arguments_around_the_separator() {
+        let saved = args(&["--release", "--", "--nocapture"]);
+        assert_eq!(
+            merge_runner_args(&saved, &args(&["-q", "--", "--test-threads=1"])),
+            args(&["--release", "-q", "--", "--nocapture", "--test-threads=1"])
+        );
+        assert_eq!(
+            merge_runner_args(&args(&["--release"]), &args(&["--", "--nocapture"])),
+            args(&["--release", "--", "--nocapture"])
+        );
+        assert_eq!(
+            merge_runner_args(&args(&["-x"]), &args(&["-vv"])),
+            args(&["-x", "-vv"])
+        );
+    }
+
+    #[test]
+    fn ignores_output_without_a_summary() {
+        assert_eq!(counts("no tests ran\n"), None);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::meta::Meta;
+use crate::submission::{Submission, SubmissionStatus};
+use crate::table;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Which statuses `list` shows.
+#[derive(Copy, Clone, Debug)]
+pub enum StatusFilter {
+    Only(SubmissionStatus),
+    All,
+}
+
+impl StatusFilter {
+    pub const NAMES: [&'static str; 4] = ["pending", "submitted", "skipped", "all"];
+
+    pub fn status(self) -> Option<SubmissionStatus> {
+        match self {
+            Self::Only(status) => Some(status),
+            Self::All => None,
+        }
+    }
+}
+
+impl FromStr for StatusFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "all" => Ok(Self::All),
+            status => status.parse().map(Self::Only),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum SortKey {
+    Name,
+    Language,
+    Status,
+    Attempts,
+}
+
+impl SortKey {
+    pub const NAMES: [&'static str; 4] = ["name", "language", "status", "attempts"];
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "name" => Ok(Self::Name),
+            "language" => Ok(Self::Language),
+            "status" => Ok(Self::Status),
+            "attempts" => Ok(Self::Attempts),
+            _ => Err(format!("unknown sort key \"{}\"", value)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    Table,
+    /// Same as `--output json`.
+    Json,
+    Csv,
+}
+
+impl ListFormat {
+    pub const NAMES: [&'static str; 3] = ["table", "json", "csv"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Table => "table",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ListFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("unknown format \"{}\"", value)),
+        }
+    }
+}
+
+/// A listed submission, with where it was downloaded to if it was.
+#[derive(Serialize)]
+pub struct Row {
+    pub exercise_name: String,
+    pub exercise_language: String,
+    pub submission_status: &'static str,
+    pub attempts: i32,
+    pub submission_token: String,
+    pub downloaded_to: Option<PathBuf>,
+}
+
+impl Row {
+    fn new(submission: Submission, downloaded: &HashMap<String, PathBuf>) -> Self {
+        Self {
+            downloaded_to: downloaded.get(&submission.submission_token).cloned(),
+            exercise_language: submission.exercise_language.to_string(),
+            submission_status: submission.submission_status.as_str(),
+            attempts: submission.attempts,
+            submission_token: submission.submission_token,
+            exercise_name: submission.exercise_name,
+        }
+    }
+}
+
+/// Finds the exercises downloaded directly under any of `dirs`, by submission token.
+/// Exercises downloaded anywhere else with `--dir` are not found.
+pub fn downloaded_exercises<'a>(dirs: impl Iterator<Item = &'a Path>) -> HashMap<String, PathBuf> {
+    let mut downloaded = HashMap::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let exercise_dir = entry.path();
+            if let Ok(meta) = Meta::load(&exercise_dir.join(".meta.json")) {
+                if let Some(token) = meta.submission_token {
+                    downloaded.insert(token, exercise_dir);
+                }
+            }
+        }
+    }
+    downloaded
+}
+
+/// Builds the rows of `submissions`, sorted by `sort` and then by name.
+pub fn rows(
+    submissions: Vec<Submission>,
+    downloaded: &HashMap<String, PathBuf>,
+    sort: SortKey,
+) -> Vec<Row> {
+    let mut rows: Vec<Row> = submissions
+        .into_iter()
+        .map(|submission| Row::new(submission, downloaded))
+        .collect();
+    rows.sort_by(|a, b| a.exercise_name.cmp(&b.exercise_name));
+    match sort {
+        SortKey::Name => (),
+        SortKey::Language => rows.sort_by(|a, b| a.exercise_language.cmp(&b.exercise_language)),
+        SortKey::Status => rows.sort_by_key(|row| {
+            let order = ["pending", "submitted", "skipped"];
+            order
+                .iter()
+                .position(|status| *status == row.submission_status)
+        }),
+        // Exercises that took the most attempts come first.
+        SortKey::Attempts => rows.sort_by_key(|row| Reverse(row.attempts)),
+    }
+    rows
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+pub fn render(rows: &[Row], format: ListFormat) -> String {
+    let header = [
+        "Exercise",
+        "Language",
+        "Status",
+        "Attempts",
+        "Token",
+        "Downloaded",
+    ];
+    let cells = |row: &Row, downloaded: String| {
+        vec![
+            row.exercise_name.clone(),
+            row.exercise_language.clone(),
+            row.submission_status.to_owned(),
+            row.attempts.to_string(),
+            row.submission_token.clone(),
+            downloaded,
+        ]
+    };
+
+    match format {
+        ListFormat::Json => unreachable!("listed as a JSON document"),
+        ListFormat::Table if rows.is_empty() => "No submissions found\n".to_owned(),
+        ListFormat::Table => {
+            let rows: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| {
+                    let downloaded = if row.downloaded_to.is_some() {
+                        "yes"
+                    } else {
+                        ""
+                    };
+                    cells(row, downloaded.to_owned())
+                })
+                .collect();
+            table::render_table(&header, &rows)
+        }
+        ListFormat::Csv => {
+            let mut output = header.join(",") + "\n";
+            for row in rows {
+                let downloaded = row
+                    .downloaded_to
+                    .as_ref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default();
+                let fields: Vec<String> = cells(row, downloaded)
+                    .iter()
+                    .map(|field| csv_field(field))
+                    .collect();
+                output.push_str(&fields.join(","));
+                output.push('\n');
+            }
+            output
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str, status: &'static str, attempts: i32) -> Row {
+        Row {
+            exercise_name: name.to_owned(),
+            exercise_language: "rust".to_owned(),
+            submission_status: status,
+            attempts,
+            submission_token: format!("{}-token", name),
+            downloaded_to: None,
+        }
+    }
+
+    #[test]
+    fn renders_aligned_table() {
+        let mut rows = vec![
+            row("answer", "pending", 0),
+            row("fizzbuzz", "submitted", 12),
+        ];
+        rows[0].downloaded_to = Some(PathBuf::from("/workspace/rust/answer"));
+        assert_eq!(
+            render(&rows, ListFormat::Table),
+            "Exercise  Language  Status     Attempts  Token           Downloaded\n\
+             answer    rust      pending    0         answer-token    yes\n\
+             fizzbuzz  rust      submitted  12        fizzbuzz-token\n"
+        );
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        let rows = vec![row("hello, \"world\"", "skipped", 1)];
+        assert_eq!(
+            render(&rows, ListFormat::Csv),
+            "Exercise,Language,Status,Attempts,Token,Downloaded\n\
+             \"hello, \"\"world\"\"\",rust,skipped,1,\"hello, \"\"world\"\"-token\",\n"
+        );
+        assert_eq!(csv_field("line\rbreak"), "\"line\rbreak\"");
+    }
+
+    #[test]
+    fn serializes_rows() {
+        let rows = vec![row("answer", "pending", 2)];
+        let json = serde_json::to_value(&rows).unwrap();
+        assert_eq!(json[0]["exercise_name"], "answer");
+        assert_eq!(json[0]["attempts"], 2);
+        assert!(json[0]["downloaded_to"].is_null());
+    }
+}
@@ -1,138 +1,857 @@
+mod api;
+mod archive;
+mod bulk;
+mod cache;
+mod checksum;
 mod config;
+mod download;
 mod error;
+mod gate;
+mod grading;
+mod http;
 mod language;
+mod listing;
+mod manifest;
 mod meta;
+mod output;
+mod reset;
+mod runner;
 mod submission;
+mod table;
+mod watch;
 
+use crate::api::{ApiClient, HttpClient};
+use crate::cache::ArchiveCache;
 use crate::config::Config;
 use crate::error::*;
 use crate::meta::Meta;
 use language::Language;
-use reqwest::Client;
+use listing::{ListFormat, SortKey, StatusFilter};
+use output::OutputFormat;
+use reset::ResetScope;
+use runner::{ProcessRunner, TestRunner};
 use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use structopt::clap::{self, ErrorKind};
 use structopt::StructOpt;
-use submission::{Submission, SubmissionStatus};
+use submission::{ExistingExercise, Submission, SubmissionQuery, SubmissionStatus};
 
 static API_URI: &str = "http://localhost:5000/api/";
+/// Environment variable overriding `API_URI`, e.g. to use `sharpener-mock-server`.
+static API_URI_VAR: &str = "SHARPENER_API_URL";
 static BUCKET_URI: &str = "https://storage.googleapis.com/";
+/// The token argument that shows the configuration instead of saving a token.
+static SHOW_CONFIG: &str = "show";
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "sharpener", about = "Sharpener CLI", after_help = exit_code::HELP)]
+struct Cli {
+    #[structopt(
+        long,
+        global = true,
+        default_value = "text",
+        possible_values = &OutputFormat::NAMES,
+        help = "Print results as text, or as versioned JSON documents for other programs, \
+                including usage errors"
+    )]
+    output: OutputFormat,
+    #[structopt(subcommand)]
+    command: Command,
+}
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "sharpener", about = "Sharpener CLI")]
 #[structopt(rename_all = "kebab-case")]
-enum Cli {
+enum Command {
     #[structopt(name = "download", about = "Download an exercise")]
     Download {
-        #[structopt(name = "token")]
-        token: String,
+        #[structopt(name = "token", required_unless = "all-pending")]
+        token: Option<String>,
+        #[structopt(
+            long,
+            conflicts_with_all = &["token", "force", "backup", "update-tests-only"],
+            help = "Download every pending exercise that is not downloaded yet"
+        )]
+        all_pending: bool,
+        #[structopt(
+            long,
+            requires = "all-pending",
+            help = "Only download pending exercises in this language"
+        )]
+        language: Option<String>,
+        #[structopt(
+            long,
+            requires = "all-pending",
+            help = "Only download pending exercises about this topic"
+        )]
+        topic: Option<String>,
+        #[structopt(
+            long,
+            conflicts_with_all = &["backup", "update-tests-only"],
+            help = "Replace an existing copy of the exercise"
+        )]
+        force: bool,
+        #[structopt(
+            long,
+            conflicts_with = "update-tests-only",
+            help = "Move an existing copy of the exercise aside before downloading"
+        )]
+        backup: bool,
+        #[structopt(long, help = "Only refresh the test files of an existing copy")]
+        update_tests_only: bool,
+        #[structopt(
+            long,
+            requires = "force",
+            help = "Let --force replace a copy of another submission"
+        )]
+        replace_other_submission: bool,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "Download into this directory instead of the workspace"
+        )]
+        dir: Option<PathBuf>,
+    },
+    #[structopt(
+        about = "Run automated tests",
+        after_help = "Runner arguments go to the test command. For Rust exercises they go to \
+                      cargo, and test harness arguments need a second separator, as in \
+                      `sharpener test answer -- --release -- --nocapture`."
+    )]
+    Test {
+        #[structopt(name = "filter", help = "Only run tests whose name matches the filter")]
+        filter: Option<String>,
+        #[structopt(long, requires = "filter", help = "Match the test name exactly")]
+        exact: bool,
+        #[structopt(long, help = "List test names without running them")]
+        list: bool,
+        #[structopt(long, help = "Rerun tests whenever exercise files change")]
+        watch: bool,
+        #[structopt(
+            long,
+            help = "Remember the runner arguments for every test run of this exercise"
+        )]
+        save_args: bool,
+        #[structopt(
+            last = true,
+            help = "Extra arguments for the test runner, with Rust harness arguments after another --"
+        )]
+        runner_args: Vec<String>,
+    },
+    #[structopt(
+        about = "List submissions, only pending ones unless told otherwise",
+        after_help = "Exercises are shown as downloaded when they are in the workspace or \
+                      the current directory."
+    )]
+    List {
+        #[structopt(
+            long,
+            conflicts_with = "all",
+            possible_values = &StatusFilter::NAMES,
+            help = "Only list submissions with this status, or all of them"
+        )]
+        status: Option<StatusFilter>,
+        #[structopt(long, help = "Only list submissions in this language")]
+        language: Option<String>,
+        #[structopt(long, help = "List submissions of every status, same as --status all")]
+        all: bool,
+        #[structopt(
+            long,
+            default_value = "name",
+            possible_values = &SortKey::NAMES,
+            help = "Order of the submissions"
+        )]
+        sort: SortKey,
+        #[structopt(
+            long,
+            possible_values = &ListFormat::NAMES,
+            help = "Output format, table by default. json is the same as --output json"
+        )]
+        format: Option<ListFormat>,
+    },
+    #[structopt(about = "Check the test files against the ones that were downloaded")]
+    Verify,
+    #[structopt(about = "Restore the current exercise to how it was downloaded")]
+    Reset {
+        #[structopt(
+            long,
+            required_unless = "all",
+            conflicts_with = "all",
+            help = "Restore the original test files"
+        )]
+        tests: bool,
+        #[structopt(
+            long,
+            help = "Restore every file to the starting point, backing up your changes"
+        )]
+        all: bool,
     },
-    #[structopt(about = "Run automated tests")]
-    Test,
-    #[structopt(about = "List pending submissions")]
-    List,
     #[structopt(about = "Show a hint for the current exercise")]
     Hint,
     #[structopt(about = "Submit current exercise solution")]
-    Submit,
+    Submit {
+        #[structopt(long, help = "Submit even if the tests fail or do not compile")]
+        force: bool,
+        #[structopt(
+            short,
+            long,
+            help = "Submit failing solutions without asking for confirmation"
+        )]
+        yes: bool,
+        #[structopt(long, help = "Wait for the solution to be graded and show the result")]
+        wait: bool,
+        #[structopt(
+            long,
+            requires = "wait",
+            help = "Seconds to wait for grading, 600 by default"
+        )]
+        timeout: Option<u64>,
+    },
+    #[structopt(about = "Show how a submission was graded, the current exercise's by default")]
+    Status {
+        #[structopt(name = "token")]
+        token: Option<String>,
+    },
     #[structopt(
         about = "Get the solution to the current exercise, and a new exercise of equivalent difficulty"
     )]
     Solution,
     #[structopt(name = "config", about = "Configure your user")]
     Config {
-        #[structopt(name = "token")]
-        token: String,
+        #[structopt(
+            name = "token",
+            help = "Your user token, or `show` to show the current configuration"
+        )]
+        token: Option<String>,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "Directory where exercises are downloaded, organized by language"
+        )]
+        workspace: Option<PathBuf>,
+        #[structopt(long, help = "Size limit of the exercise archive cache, in MiB")]
+        cache_limit: Option<u64>,
+        #[structopt(long, help = "Seconds to wait for a connection to the server")]
+        connect_timeout: Option<u64>,
+        #[structopt(
+            long,
+            help = "Seconds to wait for the server to respond or send more data"
+        )]
+        read_timeout: Option<u64>,
+        #[structopt(
+            long,
+            conflicts_with_all = &["token", "workspace", "cache-limit", "connect-timeout", "read-timeout"],
+            help = "Show the current configuration"
+        )]
+        show: bool,
+    },
+    #[structopt(about = "Manage the cache of downloaded exercise archives")]
+    Cache(CacheCommand),
+}
+
+impl Command {
+    /// Name of the command in JSON output.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Download { .. } => "download",
+            Self::Test { .. } => "test",
+            Self::List { .. } => "list",
+            Self::Verify => "verify",
+            Self::Reset { .. } => "reset",
+            Self::Hint => "hint",
+            Self::Submit { .. } => "submit",
+            Self::Status { .. } => "status",
+            Self::Solution => "solution",
+            Self::Config { show: true, .. } => "config show",
+            Self::Config {
+                token: Some(token), ..
+            } if token == SHOW_CONFIG => "config show",
+            Self::Config { .. } => "config",
+            Self::Cache(CacheCommand::List) => "cache list",
+            Self::Cache(CacheCommand::Prune { .. }) => "cache prune",
+            Self::Cache(CacheCommand::Clear) => "cache clear",
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+enum CacheCommand {
+    #[structopt(about = "List cached exercise archives")]
+    List,
+    #[structopt(about = "Remove the least recently used archives above the size limit")]
+    Prune {
+        #[structopt(long, help = "Size limit in MiB, instead of the configured one")]
+        max_size: Option<u64>,
     },
+    #[structopt(about = "Remove every cached archive")]
+    Clear,
 }
 
-fn create_client(config: Config) -> Result<Client> {
-    use reqwest::header::{self, HeaderMap, HeaderValue};
-    let value = HeaderValue::from_str(&config.token).context(InvalidToken {})?;
-    let mut headers = HeaderMap::new();
-    headers.append(header::AUTHORIZATION, value);
-    Client::builder()
-        .default_headers(headers)
-        .build()
-        .context(ClientBuild {})
+/// What commands run against, besides their arguments.
+struct Session<'a> {
+    config: Config,
+    api: &'a dyn ApiClient,
+    runner: &'a dyn TestRunner,
+    cache: ArchiveCache,
+    working_dir: PathBuf,
+    output: OutputFormat,
 }
 
-fn run_cli() -> Result<()> {
-    let args = Cli::from_args();
-    if let Cli::Config { token } = args {
-        Config::create(token)?;
-        println!("Configuration saved successfully");
+fn api_url() -> Result<reqwest::Url> {
+    let mut api_uri = env::var(API_URI_VAR).unwrap_or_else(|_| API_URI.to_owned());
+    if !api_uri.ends_with('/') {
+        api_uri.push('/');
+    }
+    reqwest::Url::parse(&api_uri).context(InvalidServerUrl { url: api_uri })
+}
+
+/// Hides all but the end of `token`, enough to tell tokens apart.
+fn masked_token(token: &str) -> String {
+    let hidden = token.chars().count().saturating_sub(4);
+    token
+        .chars()
+        .enumerate()
+        .map(|(index, c)| if index < hidden { '*' } else { c })
+        .collect()
+}
+
+fn show_config(output: OutputFormat, out: &mut dyn Write) -> Result<()> {
+    let config = Config::load()?;
+    let data = json!({
+        "config_file": Config::get_path(),
+        "token": masked_token(&config.token),
+        "workspace": config.workspace,
+        "cache_limit_mb": config.cache_limit_mb.unwrap_or(cache::DEFAULT_LIMIT_MB),
+        "connect_timeout_secs": config
+            .connect_timeout_secs
+            .unwrap_or(http::DEFAULT_CONNECT_TIMEOUT_SECS),
+        "read_timeout_secs": config
+            .read_timeout_secs
+            .unwrap_or(http::DEFAULT_READ_TIMEOUT_SECS),
+        "api_url": api_url()?.as_str(),
+    });
+    output.print(out, "config show", &data, || {
+        let workspace = config.workspace.as_ref().map_or_else(
+            || "the current directory".to_owned(),
+            |workspace| workspace.display().to_string(),
+        );
+        format!(
+            "Configuration file: {}\nToken: {}\nWorkspace: {}\nCache limit: {} MiB\nConnect timeout: {} s\nRead timeout: {} s\nServer: {}",
+            data["config_file"].as_str().unwrap_or_default(),
+            data["token"].as_str().unwrap_or_default(),
+            workspace,
+            data["cache_limit_mb"],
+            data["connect_timeout_secs"],
+            data["read_timeout_secs"],
+            data["api_url"].as_str().unwrap_or_default(),
+        )
+    })?;
+    Ok(())
+}
+
+fn run_cli(args: Cli) -> Result<()> {
+    let output = args.output;
+    let out = &mut io::stdout();
+    if let Command::Config {
+        token,
+        workspace,
+        cache_limit,
+        connect_timeout,
+        read_timeout,
+        show,
+    } = args.command
+    {
+        // `config show` predates `--show`, so "show" is never saved as a token.
+        let show_requested = token.as_deref() == Some(SHOW_CONFIG);
+        let settings = [
+            workspace.is_some(),
+            cache_limit.is_some(),
+            connect_timeout.is_some(),
+            read_timeout.is_some(),
+        ];
+        if show_requested && settings.iter().any(|given| *given) {
+            exit_with_usage_error(
+                clap::Error::with_description(
+                    "`config show` cannot be combined with other settings",
+                    ErrorKind::ArgumentConflict,
+                ),
+                output,
+            );
+        }
+        if show || show_requested {
+            return show_config(output, out);
+        }
+        // Only the given settings change, the others are kept.
+        let mut config = if Config::get_path().exists() {
+            Config::load()?
+        } else {
+            Config::default()
+        };
+        config.token = match token {
+            Some(token) => token,
+            None if !config.token.is_empty() => config.token,
+            None => exit_with_usage_error(
+                clap::Error::with_description(
+                    "The <token> argument is required to configure your user",
+                    ErrorKind::MissingRequiredArgument,
+                ),
+                output,
+            ),
+        };
+        // Relative workspaces would depend on where later commands run from.
+        if let Some(workspace) = workspace {
+            let current_dir = env::current_dir().context(IOError {})?;
+            config.workspace = Some(current_dir.join(workspace));
+        }
+        config.cache_limit_mb = cache_limit.or(config.cache_limit_mb);
+        config.connect_timeout_secs = connect_timeout.or(config.connect_timeout_secs);
+        config.read_timeout_secs = read_timeout.or(config.read_timeout_secs);
+        config.save()?;
+        output.print(
+            out,
+            "config",
+            &json!({ "config_file": Config::get_path() }),
+            || "Configuration saved successfully".to_owned(),
+        )?;
         return Ok(());
     }
 
     let config = Config::load()?;
-    let url = reqwest::Url::parse(API_URI).unwrap();
-    let client = create_client(config)?;
-
-    match args {
-        Cli::Download { token } => {
-            let submission = Submission::get(client, &url, &token)?;
-            submission.download()?;
-        }
-        Cli::List => {
-            let pending_submissions =
-                Submission::list(client, &url, Some(SubmissionStatus::Pending))?;
-
-            for submission in pending_submissions {
-                println!(
-                    "{} - {} (Submission token: {})",
-                    submission.exercise_language,
-                    submission.exercise_name,
-                    submission.submission_token
-                );
+    let api = HttpClient::new(&config, api_url()?)?;
+    let session = Session {
+        cache: config.archive_cache(),
+        config,
+        api: &api,
+        runner: &ProcessRunner,
+        working_dir: env::current_dir().context(IOError {})?,
+        output,
+    };
+    run_command(args.command, &session, out)
+}
+
+/// Runs `command`, printing its result to `out`.
+fn run_command(command: Command, session: &Session, out: &mut dyn Write) -> Result<()> {
+    let config = &session.config;
+    let output = session.output;
+    match command {
+        Command::Download {
+            all_pending: true,
+            language,
+            topic,
+            dir,
+            ..
+        } => {
+            let query = SubmissionQuery {
+                language,
+                topic,
+                ..SubmissionQuery::default()
+            };
+            bulk::download_pending(
+                session.api,
+                config,
+                &session.cache,
+                dir.as_deref(),
+                &query,
+                output,
+                out,
+            )?;
+        }
+        Command::Download {
+            token,
+            force,
+            backup,
+            update_tests_only,
+            replace_other_submission,
+            dir,
+            ..
+        } => {
+            let existing = match (force, backup, update_tests_only) {
+                (true, _, _) if replace_other_submission => ExistingExercise::OverwriteForeign,
+                (true, _, _) => ExistingExercise::Overwrite,
+                (_, true, _) => ExistingExercise::Backup,
+                (_, _, true) => ExistingExercise::UpdateTests,
+                _ => ExistingExercise::Refuse,
+            };
+            let token = token.unwrap();
+            let submission = session.api.get(&token)?;
+            let destination =
+                dir.unwrap_or_else(|| config.download_dir(&submission.exercise_language));
+            let exercise_dir = submission.download(
+                session.api,
+                &session.cache,
+                &destination,
+                existing,
+                output.is_text(),
+            )?;
+            let data = json!({
+                "exercise_name": submission.exercise_name,
+                "exercise_language": submission.exercise_language.as_str(),
+                "submission_token": submission.submission_token,
+                "exercise_dir": exercise_dir,
+            });
+            output.print(out, "download", &data, || {
+                format!(
+                    "Exercise downloaded to {}. To start working on it, run:\n\n\tcd {}\n",
+                    exercise_dir.display(),
+                    exercise_dir.display()
+                )
+            })?;
+        }
+        Command::List {
+            status,
+            language,
+            all,
+            sort,
+            format,
+        } => {
+            let output = match format {
+                Some(ListFormat::Json) => OutputFormat::Json,
+                Some(format) => {
+                    ensure!(
+                        output.is_text(),
+                        ListFormatJsonOutput {
+                            format: format.as_str()
+                        }
+                    );
+                    output
+                }
+                None => output,
+            };
+            let format = format.unwrap_or(ListFormat::Table);
+            let status = match (all, status) {
+                (true, _) => StatusFilter::All,
+                (_, Some(status)) => status,
+                _ => StatusFilter::Only(SubmissionStatus::Pending),
+            };
+            let query = SubmissionQuery {
+                status: status.status(),
+                language,
+                ..SubmissionQuery::default()
+            };
+            let submissions = Submission::list(session.api, &query)?;
+
+            // Exercises downloaded with --dir are only found from where they were
+            // downloaded to.
+            let download_dirs: BTreeSet<PathBuf> = submissions
+                .iter()
+                .map(|submission| {
+                    let dir = config.download_dir(&submission.exercise_language);
+                    session.working_dir.join(dir)
+                })
+                .chain(Some(session.working_dir.clone()))
+                .collect();
+            let downloaded =
+                listing::downloaded_exercises(download_dirs.iter().map(PathBuf::as_path));
+            let rows = listing::rows(submissions, &downloaded, sort);
+            output.print(out, "list", &rows, || {
+                listing::render(&rows, format).trim_end().to_owned()
+            })?;
+        }
+        Command::Test { list: true, .. } => {
+            let (meta, path) = Meta::find(&session.working_dir)?;
+            let mut list_command = meta.language.list_tests_command();
+            list_command.current_dir(path.parent().unwrap());
+            if output.is_text() {
+                session.runner.run_attached(list_command)?;
+            } else {
+                let test_run = session.runner.run_captured(list_command, false)?;
+                output.print_data(out, "test", &json!({ "output": test_run.stdout }))?;
             }
         }
-        Cli::Test => {
-            let (meta, _) = Meta::get()?;
-            let mut test_command = meta.language.test_command();
-            test_command
-                .spawn()
-                .and_then(|mut child| child.wait())
-                .context(TestCommand {})?;
+        Command::Test {
+            filter,
+            exact,
+            watch,
+            save_args,
+            runner_args,
+            ..
+        } => {
+            let (mut meta, path) = Meta::find(&session.working_dir)?;
+            let runner_args = if save_args {
+                meta.test_args = Some(runner_args).filter(|args| !args.is_empty());
+                meta.write(&path)?;
+                meta.test_args.clone().unwrap_or_default()
+            } else {
+                let saved_args = meta.test_args.as_deref().unwrap_or(&[]);
+                language::merge_runner_args(saved_args, &runner_args)
+            };
+
+            let exercise_dir = path.parent().unwrap();
+            manifest::warn_if_modified(&meta.language, exercise_dir, meta.test_checksums.as_ref())?;
+            let test_command = || {
+                let mut command =
+                    meta.language
+                        .custom_test_command(filter.as_deref(), exact, &runner_args);
+                command.current_dir(exercise_dir);
+                command
+            };
+
+            if watch {
+                ensure!(output.is_text(), WatchJsonOutput);
+                watch::watch_tests(&meta, &path, &test_command)?;
+                return Ok(());
+            }
+
+            if output.is_text() {
+                let exit_code = session.runner.run_attached(test_command())?;
+                ensure!(exit_code == Some(0), TestsFailed { exit_code });
+                return Ok(());
+            }
+            let test_run = session.runner.run_captured(test_command(), false)?;
+            let results = meta.language.parse_test_results(&test_run.stdout);
+            output.print_data(
+                out,
+                "test",
+                &json!({
+                    "success": test_run.success(),
+                    "exit_code": test_run.exit_code,
+                    "passed": results.as_ref().map(|results| results.passed),
+                    "failed": results.as_ref().map(|results| results.failed),
+                    "stdout": test_run.stdout,
+                    "stderr": test_run.stderr,
+                }),
+            )?;
+            ensure!(
+                test_run.success(),
+                TestsFailed {
+                    exit_code: test_run.exit_code
+                }
+            );
         }
-        Cli::Hint => {
-            let (mut meta, path) = Meta::get()?;
+        Command::Verify => {
+            let (meta, path) = Meta::find(&session.working_dir)?;
+            let test_checksums = meta.test_checksums.as_ref().context(MissingManifest {})?;
+            let diff = manifest::compare(&meta.language, path.parent().unwrap(), test_checksums)?;
+            ensure!(
+                diff.is_clean(),
+                ModifiedTests {
+                    diff: diff.to_string()
+                }
+            );
+            output.print(out, "verify", &json!({ "clean": true }), || {
+                "All test files match the downloaded exercise".to_owned()
+            })?;
+        }
+        Command::Reset { tests, all } => {
+            let scope = match (tests, all) {
+                (_, true) => ResetScope::All,
+                _ => ResetScope::Tests,
+            };
+            let restored = reset::reset(session.api, &session.cache, &session.working_dir, scope)?;
+            let backup_dir = Some(&restored.backup_dir).filter(|_| restored.backed_up > 0);
+            let data = json!({
+                "scope": if scope == ResetScope::All { "all" } else { "tests" },
+                "backed_up": restored.backed_up,
+                "backup_dir": backup_dir,
+            });
+            output.print(out, "reset", &data, || {
+                let mut text = match scope {
+                    ResetScope::Tests => "Test files restored".to_owned(),
+                    ResetScope::All => "Exercise restored to its starting point".to_owned(),
+                };
+                if let Some(backup_dir) = backup_dir {
+                    text.push_str(&format!(
+                        "\nYour previous version of {} file(s) was saved to {}",
+                        restored.backed_up,
+                        backup_dir.display()
+                    ));
+                }
+                text
+            })?;
+        }
+        Command::Hint => {
+            let (mut meta, path) = Meta::find(&session.working_dir)?;
             match meta.hints.as_ref() {
                 None => {
-                    println!("This exercise has no hints. Good luck!");
+                    output.print(out, "hint", &json!({ "hints": [], "total": 0 }), || {
+                        "This exercise has no hints. Good luck!".to_owned()
+                    })?;
                     return Ok(());
                 }
                 Some(hints) => {
                     let hints_seen = std::cmp::min(meta.hints_seen.unwrap_or(0) + 1, hints.len());
-                    for (index, hint) in hints[..hints_seen].iter().enumerate() {
-                        println!("Hint #{}:\n{}\n", index + 1, hint);
-                    }
+                    let revealed = &hints[..hints_seen];
+                    let data = json!({ "hints": revealed, "total": hints.len() });
+                    output.print(out, "hint", &data, || {
+                        let revealed: Vec<String> = revealed
+                            .iter()
+                            .enumerate()
+                            .map(|(index, hint)| format!("Hint #{}:\n{}\n", index + 1, hint))
+                            .collect();
+                        revealed.join("\n")
+                    })?;
 
                     meta.hints_seen = Some(hints_seen);
                     meta.write(&path)?;
                 }
             }
         }
-        Cli::Solution => {
-            let (meta, _) = Meta::get()?;
-            let submission = Submission::forfeit(client, &url, &meta)?;
-            println!(
-                "Exercise successfully forteited. To download the replacement exercise, run the following command:\n\n\tsharpener download {}\n", 
-                submission.submission_token
-            );
+        Command::Solution => {
+            let (meta, _) = Meta::find(&session.working_dir)?;
+            let token = meta.submission_token.as_ref().context(MissingMeta {})?;
+            let submission = session.api.forfeit(token)?;
+            let data = json!({
+                "forfeited_token": token,
+                "replacement": {
+                    "exercise_name": submission.exercise_name,
+                    "exercise_language": submission.exercise_language.as_str(),
+                    "submission_token": submission.submission_token,
+                },
+            });
+            output.print(out, "solution", &data, || {
+                format!(
+                    "Exercise successfully forteited. To download the replacement exercise, run the following command:\n\n\tsharpener download {}\n", 
+                    submission.submission_token
+                )
+            })?;
+        }
+        Command::Submit {
+            force,
+            yes,
+            wait,
+            timeout,
+        } => {
+            let mut submitted = Submission::submit(
+                session.api,
+                session.runner,
+                &session.working_dir,
+                force,
+                yes,
+                output.is_text(),
+            )?;
+            let token = submitted.submission_token.clone();
+            let waited = match submitted.grading.as_mut() {
+                Some(started) if wait && !started.is_done() => {
+                    if output.is_text() {
+                        writeln!(out, "{}", started).context(IOError {})?;
+                    }
+                    let timeout = timeout.unwrap_or(grading::DEFAULT_WAIT_SECS);
+                    grading::wait(
+                        session.api,
+                        &token,
+                        started,
+                        grading::POLL_INTERVAL,
+                        Duration::from_secs(timeout),
+                        |grading| {
+                            if output.is_text() && !grading.is_done() {
+                                // Progress is best effort, like echoed test output.
+                                let _ = writeln!(out, "{}", grading);
+                            }
+                        },
+                    )
+                }
+                _ => Ok(()),
+            };
+            // The solution was sent even when grading takes too long, so it is
+            // always shown, as last seen.
+            output.print(out, "submit", &submitted, || match &submitted.grading {
+                Some(grading) => format!("Solution submitted\n{}", grading),
+                None => "Solution submitted".to_owned(),
+            })?;
+            waited?;
+        }
+        Command::Status { token } => {
+            let token = match token {
+                Some(token) => token,
+                None => {
+                    let (meta, _) = Meta::find(&session.working_dir)?;
+                    meta.submission_token.context(MissingMeta {})?
+                }
+            };
+            let grading = match session.api.grading(&token) {
+                // Submissions that exist have no grading until a solution is sent.
+                Err(Error::ExerciseNotFound { .. }) if session.api.get(&token).is_ok() => {
+                    NotSubmitted { token: &token }.fail()
+                }
+                result => result,
+            }?;
+            let data = json!({ "submission_token": token, "grading": grading });
+            output.print(out, "status", &data, || grading.to_string())?;
+        }
+        Command::Cache(CacheCommand::List) => {
+            let entries = session.cache.entries()?;
+            let data: Vec<_> = entries
+                .iter()
+                .map(|entry| {
+                    json!({
+                        "exercise_name": entry.exercise_name,
+                        "download_url": entry.download_url,
+                        "digest": entry.digest,
+                        "size": entry.size,
+                        "last_used": entry.last_used,
+                    })
+                })
+                .collect();
+            output.print(out, "cache list", &data, || {
+                if entries.is_empty() {
+                    return "The cache is empty".to_owned();
+                }
+                let lines: Vec<String> = entries
+                    .iter()
+                    .map(|entry| {
+                        format!(
+                            "{} - {:.1} MiB (sha256:{})",
+                            entry.exercise_name,
+                            entry.size as f64 / 1024.0 / 1024.0,
+                            entry.digest
+                        )
+                    })
+                    .collect();
+                lines.join("\n")
+            })?;
+        }
+        Command::Cache(CacheCommand::Prune { max_size }) => {
+            let cache = &session.cache;
+            let limit = max_size.map_or(cache.limit(), |size| size * 1024 * 1024);
+            let removed = cache.prune(limit)?;
+            output.print(out, "cache prune", &json!({ "removed": removed }), || {
+                format!("Removed {} archive(s) from the cache", removed)
+            })?;
         }
-        Cli::Submit => {
-            Submission::submit(client, &url)?;
+        Command::Cache(CacheCommand::Clear) => {
+            session.cache.clear()?;
+            output.print(out, "cache clear", &json!({}), || {
+                "Cache cleared".to_owned()
+            })?;
         }
-        Cli::Config { .. } => unreachable!(),
+        Command::Config { .. } => unreachable!(),
     }
     Ok(())
 }
 
+/// Exits like clap does, but with the documented exit code for usage errors,
+/// which are printed as JSON documents when asked to.
+fn exit_with_usage_error(error: clap::Error, output: OutputFormat) -> ! {
+    match error.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => error.exit(),
+        _ => {
+            output.print_usage_error(&error.message);
+            process::exit(exit_code::USAGE);
+        }
+    }
+}
+
 fn main() {
-    match run_cli() {
+    let args = match Cli::from_args_safe() {
+        Ok(args) => args,
+        Err(error) => {
+            let args: Vec<String> = env::args().collect();
+            exit_with_usage_error(error, OutputFormat::requested_in(&args))
+        }
+    };
+    let output = args.output;
+    let command = args.command.name();
+    match run_cli(args) {
         Ok(_) => (),
         Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+            output.print_error(command, &e);
+            process::exit(e.exit_code());
         }
     }
 }
@@ -152,3 +871,706 @@ struct Exercise {
     hint: Option<String>,
     topics: Option<Vec<String>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::fake::FakeApi;
+    use crate::grading::{Grading, GradingState};
+    use crate::runner::fake::FakeRunner;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn exercise_archive(name: &str) -> Vec<u8> {
+        exercise_archive_in(name, "rust")
+    }
+
+    fn exercise_archive_in(name: &str, language: &str) -> Vec<u8> {
+        let files = vec![
+            (
+                ".meta.json",
+                format!(
+                    r#"{{"name": "{}", "language": "{}", "difficulty": 1, "hints": ["Read the tests", "Return 42"]}}"#,
+                    name, language
+                ),
+            ),
+            (
+                "Cargo.toml",
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n[workspace]\n",
+                    name
+                ),
+            ),
+            ("src/lib.rs", "pub fn answer() -> u32 {\n    42\n}\n".to_owned()),
+            (
+                "tests/tests.rs",
+                format!(
+                    "#[test]\nfn answer() {{\n    assert_eq!({}::answer(), 42);\n}}\n",
+                    name
+                ),
+            ),
+        ];
+
+        let encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(format!("{}/{}", name, path)).unwrap();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn submission(name: &str, token: &str, status: SubmissionStatus) -> Submission {
+        Submission {
+            exercise_name: name.to_owned(),
+            exercise_language: Language::Rust,
+            download_url: format!("https://storage.example.com/{}.tar.gz", name),
+            submission_token: token.to_owned(),
+            attempts: 0,
+            submission_status: status,
+            exercise_topics: vec!["basics".to_owned()],
+            archive_checksum: None,
+            archive_size: None,
+        }
+    }
+
+    fn with_exercise(api: FakeApi, name: &str, token: &str) -> FakeApi {
+        let archive = exercise_archive(name);
+        let mut submission = submission(name, token, SubmissionStatus::Pending);
+        submission.archive_checksum = Some(checksum::checksum_bytes(
+            &archive,
+            checksum::DEFAULT_ALGORITHM,
+        ));
+        submission.archive_size = Some(archive.len() as u64);
+        api.with_submission(submission, Some(archive))
+    }
+
+    const PASSING_TESTS: &str = "test result: ok. 1 passed; 0 failed; 0 ignored\n";
+
+    struct Fixture {
+        api: FakeApi,
+        runner: FakeRunner,
+        workspace: TempDir,
+        cache: TempDir,
+    }
+
+    impl Fixture {
+        fn new(api: FakeApi) -> Self {
+            Self {
+                api,
+                runner: FakeRunner::new(PASSING_TESTS, Some(0)),
+                workspace: tempfile::tempdir().unwrap(),
+                cache: tempfile::tempdir().unwrap(),
+            }
+        }
+
+        fn with_runner(self, runner: FakeRunner) -> Self {
+            Self { runner, ..self }
+        }
+
+        fn exercise_dir(&self, name: &str) -> PathBuf {
+            self.workspace.path().join("rust").join(name)
+        }
+
+        /// Runs a command and returns what it printed.
+        fn run_in(&self, working_dir: &Path, args: &[&str]) -> Result<String> {
+            let args = Cli::from_iter_safe(Some("sharpener").iter().chain(args)).unwrap();
+            let session = Session {
+                config: Config {
+                    token: "user-token".to_owned(),
+                    workspace: Some(self.workspace.path().to_path_buf()),
+                    ..Config::default()
+                },
+                api: &self.api,
+                runner: &self.runner,
+                cache: ArchiveCache::in_dir(self.cache.path().to_path_buf(), 64),
+                working_dir: working_dir.to_path_buf(),
+                output: args.output,
+            };
+            let mut out = Vec::new();
+            run_command(args.command, &session, &mut out)?;
+            Ok(String::from_utf8(out).unwrap())
+        }
+
+        fn run(&self, args: &[&str]) -> Result<String> {
+            self.run_in(self.workspace.path(), args)
+        }
+
+        fn downloads(&self) -> usize {
+            self.api.downloads.lock().unwrap().len()
+        }
+    }
+
+    #[test]
+    fn download_unpacks_exercise_into_workspace() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+
+        let meta = Meta::load(&fixture.exercise_dir("hello").join(".meta.json")).unwrap();
+        assert_eq!(meta.submission_token.as_deref(), Some("token-1"));
+        assert!(meta.test_checksums.unwrap().contains_key("tests/tests.rs"));
+        assert_eq!(fixture.downloads(), 1);
+    }
+
+    #[test]
+    fn download_into_chosen_directory() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        let dir = tempfile::tempdir().unwrap();
+        let dir_arg = dir.path().to_str().unwrap();
+        fixture
+            .run(&["download", "token-1", "--dir", dir_arg])
+            .unwrap();
+        assert!(dir.path().join("hello/.meta.json").is_file());
+    }
+
+    #[test]
+    fn download_unpacks_in_the_served_format() {
+        let compressed = exercise_archive("hello");
+        let mut uncompressed = Vec::new();
+        let mut tarball = flate2::read::GzDecoder::new(&compressed[..]);
+        std::io::Read::read_to_end(&mut tarball, &mut uncompressed).unwrap();
+        // A leading skippable frame hides the zstd magic bytes.
+        let mut archive = vec![0x50, 0x2a, 0x4d, 0x18, 0, 0, 0, 0];
+        archive.extend(zstd::stream::encode_all(&uncompressed[..], 0).unwrap());
+        let hello = submission("hello", "token-1", SubmissionStatus::Pending);
+        let api = FakeApi::default()
+            .with_content_type(&hello.download_url, "application/zstd")
+            .with_submission(hello, Some(archive));
+        let fixture = Fixture::new(api);
+        fixture.run(&["download", "token-1"]).unwrap();
+
+        // Resetting unpacks the cached copy, which keeps the format.
+        let exercise_dir = fixture.exercise_dir("hello");
+        fs::remove_file(exercise_dir.join("tests/tests.rs")).unwrap();
+        fixture
+            .run_in(&exercise_dir, &["reset", "--tests"])
+            .unwrap();
+        assert!(exercise_dir.join("tests/tests.rs").is_file());
+        assert_eq!(fixture.downloads(), 1);
+    }
+
+    #[test]
+    fn download_works_without_a_usable_cache() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fs::remove_dir(fixture.cache.path()).unwrap();
+        fs::write(fixture.cache.path(), "not a directory").unwrap();
+        let result = fixture.run(&["download", "token-1"]);
+        fs::remove_file(fixture.cache.path()).unwrap();
+        result.unwrap();
+        assert!(fixture.exercise_dir("hello").join(".meta.json").is_file());
+    }
+
+    #[test]
+    fn download_of_unknown_submission_fails() {
+        let fixture = Fixture::new(FakeApi::default());
+        let result = fixture.run(&["download", "missing"]);
+        assert!(matches!(result, Err(Error::ExerciseNotFound { .. })));
+    }
+
+    #[test]
+    fn download_handles_existing_exercise() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let solution = fixture.exercise_dir("hello").join("src/lib.rs");
+        fs::write(&solution, "// my work").unwrap();
+
+        let refused = fixture.run(&["download", "token-1"]);
+        assert!(matches!(refused, Err(Error::ExerciseExists { .. })));
+
+        fixture.run(&["download", "token-1", "--backup"]).unwrap();
+        let backups = fs::read_dir(fixture.workspace.path().join("rust"))
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with("hello.backup-")
+            })
+            .count();
+        assert_eq!(backups, 1);
+        assert_ne!(fs::read_to_string(&solution).unwrap(), "// my work");
+
+        fs::write(&solution, "// my work").unwrap();
+        fixture.run(&["download", "token-1", "--force"]).unwrap();
+        assert_ne!(fs::read_to_string(&solution).unwrap(), "// my work");
+        // Later downloads are served from the archive cache.
+        assert_eq!(fixture.downloads(), 1);
+    }
+
+    #[test]
+    fn download_keeps_copies_it_may_not_replace() {
+        // The archive is unsafe to unpack, as its entries are outside of hello/.
+        let api = FakeApi::default().with_submission(
+            submission("hello", "token-1", SubmissionStatus::Pending),
+            Some(exercise_archive("other")),
+        );
+        let fixture = Fixture::new(api);
+        let meta_path = fixture.exercise_dir("hello").join(".meta.json");
+        fs::create_dir_all(meta_path.parent().unwrap()).unwrap();
+        let write_meta = |token: &str| {
+            let meta = format!(
+                r#"{{"name": "hello", "language": "rust", "difficulty": 1, "submission_token": "{}"}}"#,
+                token
+            );
+            fs::write(&meta_path, meta).unwrap();
+        };
+
+        write_meta("token-2");
+        let refused = fixture.run(&["download", "token-1"]);
+        assert!(matches!(refused, Err(Error::ExerciseExists { .. })));
+        let refused = fixture.run(&["download", "token-1", "--force"]);
+        assert!(matches!(refused, Err(Error::ForeignExercise { .. })));
+        assert_eq!(fixture.downloads(), 0);
+
+        let result = fixture.run(&[
+            "download",
+            "token-1",
+            "--force",
+            "--replace-other-submission",
+        ]);
+        assert!(matches!(result, Err(Error::UnsafeArchive { .. })));
+        write_meta("token-1");
+        let result = fixture.run(&["download", "token-1", "--force"]);
+        assert!(matches!(result, Err(Error::UnsafeArchive { .. })));
+        assert!(meta_path.is_file());
+        let leftovers = fs::read_dir(fixture.workspace.path().join("rust")).unwrap();
+        assert_eq!(leftovers.count(), 1);
+    }
+
+    #[test]
+    fn download_all_pending_skips_other_submissions() {
+        let api = with_exercise(FakeApi::default(), "first", "token-1");
+        let api = with_exercise(api, "second", "token-2");
+        let api = api.with_submission(
+            submission("done", "token-3", SubmissionStatus::Submitted),
+            Some(exercise_archive("done")),
+        );
+        let fixture = Fixture::new(api);
+        fixture.run(&["download", "--all-pending"]).unwrap();
+
+        assert!(fixture.exercise_dir("first").is_dir());
+        assert!(fixture.exercise_dir("second").is_dir());
+        assert!(!fixture.exercise_dir("done").exists());
+    }
+
+    #[test]
+    fn download_all_pending_reports_failures() {
+        let api = with_exercise(FakeApi::default(), "first", "token-1").with_submission(
+            submission("broken", "token-2", SubmissionStatus::Pending),
+            None,
+        );
+        let fixture = Fixture::new(api);
+        let result = fixture.run(&["download", "--all-pending", "--topic", "basics"]);
+        assert!(matches!(
+            result,
+            Err(Error::BulkDownload {
+                failed: 1,
+                total: 2
+            })
+        ));
+        assert!(fixture.exercise_dir("first").is_dir());
+    }
+
+    #[test]
+    fn download_refuses_names_outside_the_destination() {
+        let outside = tempfile::tempdir().unwrap();
+        let names = [
+            "../escape".to_owned(),
+            outside.path().display().to_string(),
+            "nested/name".to_owned(),
+        ];
+        let api = names
+            .iter()
+            .enumerate()
+            .fold(FakeApi::default(), |api, (index, name)| {
+                let token = format!("token-{}", index);
+                api.with_submission(submission(name, &token, SubmissionStatus::Pending), None)
+            });
+        let fixture = Fixture::new(api);
+        let escape = fixture.workspace.path().join("escape");
+        fs::create_dir(&escape).unwrap();
+        let dir_arg = escape.to_str().unwrap();
+
+        for (index, name) in names.iter().enumerate() {
+            let token = format!("token-{}", index);
+            let result = fixture.run(&["download", &token, "--backup", "--dir", dir_arg]);
+            assert!(
+                matches!(result, Err(Error::UnsafeArchive { .. })),
+                "{}",
+                name
+            );
+        }
+        assert!(outside.path().is_dir());
+        assert!(escape.is_dir());
+        assert_eq!(fixture.downloads(), 0);
+    }
+
+    #[test]
+    fn download_accepts_languages_without_known_tests() {
+        let mut submission = submission("hello", "token-1", SubmissionStatus::Pending);
+        submission.exercise_language = Language::Other("go".to_owned());
+        let api = FakeApi::default()
+            .with_submission(submission, Some(exercise_archive_in("hello", "go")));
+        let fixture = Fixture::new(api);
+        fixture.run(&["download", "token-1"]).unwrap();
+
+        let exercise_dir = fixture.workspace.path().join("go").join("hello");
+        let meta = Meta::load(&exercise_dir.join(".meta.json")).unwrap();
+        assert_eq!(meta.submission_token.as_deref(), Some("token-1"));
+        assert!(meta.test_checksums.is_none());
+    }
+
+    #[test]
+    fn list_shows_pending_submissions() {
+        let api = with_exercise(FakeApi::default(), "hello", "token-1").with_submission(
+            submission("done", "token-2", SubmissionStatus::Submitted),
+            None,
+        );
+        let fixture = Fixture::new(api);
+        let pending = fixture.run(&["list"]).unwrap();
+        assert!(pending.starts_with("Exercise  Language  Status   Attempts  Token"));
+        assert!(pending.contains("\nhello     rust      pending  0         token-1"));
+        assert!(!pending.contains("done"));
+
+        let all = fixture
+            .run(&["list", "--all", "--sort", "attempts"])
+            .unwrap();
+        assert_eq!(all.lines().count(), 3);
+        let document: serde_json::Value = serde_json::from_str(
+            &fixture
+                .run(&["list", "--status", "all", "--format", "json"])
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(document["schema_version"], 1);
+        assert_eq!(document["data"][0]["exercise_name"], "done");
+        assert_eq!(document["data"][1]["submission_token"], "token-1");
+        let result = fixture.run(&["list", "--format", "csv", "--output", "json"]);
+        assert!(matches!(result, Err(Error::ListFormatJsonOutput { .. })));
+        let python = fixture
+            .run(&["list", "--status", "submitted", "--language", "python"])
+            .unwrap();
+        assert_eq!(python, "No submissions found\n");
+
+        fixture.run(&["download", "token-1"]).unwrap();
+        let csv = fixture.run(&["list", "--format", "csv"]).unwrap();
+        let downloaded = format!(
+            "hello,rust,pending,0,token-1,{}\n",
+            fixture.exercise_dir("hello").display()
+        );
+        assert!(csv.ends_with(&downloaded), "{}", csv);
+    }
+
+    #[test]
+    fn list_finds_exercises_downloaded_to_the_current_directory() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        let dir = tempfile::tempdir().unwrap();
+        let dir_arg = dir.path().to_str().unwrap();
+        fixture
+            .run(&["download", "token-1", "--dir", dir_arg])
+            .unwrap();
+
+        let elsewhere = fixture.run(&["list", "--format", "csv"]).unwrap();
+        assert!(elsewhere.ends_with("token-1,\n"));
+        let here = fixture
+            .run_in(dir.path(), &["list", "--format", "csv"])
+            .unwrap();
+        let downloaded = format!("token-1,{}\n", dir.path().join("hello").display());
+        assert!(here.ends_with(&downloaded), "{}", here);
+    }
+
+    #[test]
+    fn commands_print_json() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        let exercise_dir = fixture.exercise_dir("hello");
+        let json = |dir: &Path, args: &[&str]| -> serde_json::Value {
+            let printed = fixture.run_in(dir, &[args, &["--output", "json"]].concat());
+            let document: serde_json::Value = serde_json::from_str(&printed.unwrap()).unwrap();
+            assert_eq!(document["schema_version"], 1);
+            assert_eq!(document["command"], args[0]);
+            document
+        };
+
+        let list = json(fixture.workspace.path(), &["list"]);
+        assert_eq!(list["data"][0]["submission_token"], "token-1");
+        let download = json(fixture.workspace.path(), &["download", "token-1"]);
+        assert_eq!(
+            download["data"]["exercise_dir"],
+            exercise_dir.to_str().unwrap()
+        );
+        let hint = json(&exercise_dir, &["hint"]);
+        assert_eq!(hint["data"]["hints"][0], "Read the tests");
+        let verify = json(&exercise_dir, &["verify"]);
+        assert_eq!(verify["data"]["clean"], true);
+        let test = json(&exercise_dir, &["test"]);
+        assert_eq!(test["data"]["passed"], 1);
+        let submit = json(&exercise_dir, &["submit"]);
+        assert_eq!(submit["data"]["verdict"], "passing");
+
+        let result = fixture.run_in(&exercise_dir, &["test", "--watch", "--output", "json"]);
+        assert!(matches!(result, Err(Error::WatchJsonOutput)));
+    }
+
+    #[test]
+    fn test_runs_the_tests_of_the_exercise() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"))
+            .with_runner(FakeRunner::new("", Some(101)));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+
+        let result = fixture.run_in(
+            &exercise_dir,
+            &["test", "answer", "--", "--release", "--", "--nocapture"],
+        );
+        assert!(matches!(
+            result,
+            Err(Error::TestsFailed {
+                exit_code: Some(101)
+            })
+        ));
+        let commands = fixture.runner.commands.lock().unwrap();
+        assert_eq!(
+            commands.last().unwrap(),
+            &["cargo", "test", "--release", "--", "answer", "--nocapture"]
+        );
+    }
+
+    #[test]
+    fn test_merges_saved_runner_arguments() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+
+        fixture
+            .run_in(&exercise_dir, &["test", "--save-args", "--", "--release"])
+            .unwrap();
+        let meta = Meta::load(&exercise_dir.join(".meta.json")).unwrap();
+        assert_eq!(meta.test_args, Some(vec!["--release".to_owned()]));
+        fixture
+            .run_in(&exercise_dir, &["test", "--", "--", "--nocapture"])
+            .unwrap();
+        fixture
+            .run_in(&exercise_dir, &["test", "--save-args"])
+            .unwrap();
+        fixture.run_in(&exercise_dir, &["test"]).unwrap();
+
+        let commands = fixture.runner.commands.lock().unwrap();
+        assert_eq!(
+            commands[commands.len() - 4..],
+            [
+                vec!["cargo", "test", "--release", "--"],
+                vec!["cargo", "test", "--release", "--", "--nocapture"],
+                vec!["cargo", "test", "--"],
+                vec!["cargo", "test", "--"],
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_all_backs_up_changed_files() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        let solution = exercise_dir.join("src/lib.rs");
+        let original = fs::read_to_string(&solution).unwrap();
+        fs::write(&solution, "// my work").unwrap();
+
+        let printed = fixture
+            .run_in(&exercise_dir, &["reset", "--all", "--output", "json"])
+            .unwrap();
+        let document: serde_json::Value = serde_json::from_str(&printed).unwrap();
+        assert_eq!(document["data"]["scope"], "all");
+        assert_eq!(document["data"]["backed_up"], 1);
+        assert_eq!(fs::read_to_string(&solution).unwrap(), original);
+        let backup_dir = PathBuf::from(document["data"]["backup_dir"].as_str().unwrap());
+        let backup = fs::read_to_string(backup_dir.join("src/lib.rs")).unwrap();
+        assert_eq!(backup, "// my work");
+    }
+
+    #[test]
+    fn verify_detects_modified_tests() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        fixture.run_in(&exercise_dir, &["verify"]).unwrap();
+
+        fs::write(exercise_dir.join("tests/tests.rs"), "").unwrap();
+        let result = fixture.run_in(&exercise_dir, &["verify"]);
+        assert!(matches!(result, Err(Error::ModifiedTests { .. })));
+    }
+
+    #[test]
+    fn reset_downloads_the_exercise_again_when_not_cached() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        let tests = exercise_dir.join("tests/tests.rs");
+        let original = fs::read_to_string(&tests).unwrap();
+        fs::write(&tests, "").unwrap();
+
+        fixture.run(&["cache", "clear"]).unwrap();
+        fixture
+            .run_in(&exercise_dir.join("src"), &["reset", "--tests"])
+            .unwrap();
+        assert_eq!(fs::read_to_string(&tests).unwrap(), original);
+        assert_eq!(fixture.downloads(), 2);
+    }
+
+    #[test]
+    fn reset_restores_a_removed_test_directory() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        let tests = exercise_dir.join("tests");
+
+        fs::remove_dir_all(&tests).unwrap();
+        fixture
+            .run_in(&exercise_dir, &["reset", "--tests"])
+            .unwrap();
+        assert!(tests.join("tests.rs").is_file());
+
+        fs::remove_dir_all(&tests).unwrap();
+        fixture
+            .run(&["download", "token-1", "--update-tests-only"])
+            .unwrap();
+        assert!(tests.join("tests.rs").is_file());
+    }
+
+    #[test]
+    fn reset_unpacks_the_archive_like_download() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        let meta_path = exercise_dir.join(".meta.json");
+        let mut meta = Meta::load(&meta_path).unwrap();
+        assert_eq!(meta.archive_root.as_deref(), Some("hello"));
+        meta.name = "Hello, World!".to_owned();
+        meta.write(&meta_path).unwrap();
+
+        fs::write(exercise_dir.join("tests/tests.rs"), "").unwrap();
+        fixture
+            .run_in(&exercise_dir, &["reset", "--tests"])
+            .unwrap();
+        fixture.run_in(&exercise_dir, &["verify"]).unwrap();
+    }
+
+    #[test]
+    fn hint_reveals_one_more_hint_each_time() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        let hints_seen = || {
+            Meta::load(&exercise_dir.join(".meta.json"))
+                .unwrap()
+                .hints_seen
+        };
+
+        fixture.run_in(&exercise_dir, &["hint"]).unwrap();
+        assert_eq!(hints_seen(), Some(1));
+        fixture.run_in(&exercise_dir, &["hint"]).unwrap();
+        fixture.run_in(&exercise_dir, &["hint"]).unwrap();
+        assert_eq!(hints_seen(), Some(2));
+    }
+
+    #[test]
+    fn commands_need_an_exercise() {
+        let fixture = Fixture::new(FakeApi::default());
+        for command in &["hint", "verify", "solution", "submit"] {
+            let result = fixture.run(&[command]);
+            assert!(matches!(result, Err(Error::MissingMeta)), "{}", command);
+        }
+    }
+
+    #[test]
+    fn solution_forfeits_the_current_exercise() {
+        let api = with_exercise(FakeApi::default(), "hello", "token-1").with_replacement(
+            "token-1",
+            submission("other", "token-2", SubmissionStatus::Pending),
+        );
+        let fixture = Fixture::new(api);
+        fixture.run(&["download", "token-1"]).unwrap();
+        fixture
+            .run_in(&fixture.exercise_dir("hello"), &["solution"])
+            .unwrap();
+        assert_eq!(*fixture.api.forfeited.lock().unwrap(), vec!["token-1"]);
+    }
+
+    #[test]
+    fn solution_without_replacement_fails() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let result = fixture.run_in(&fixture.exercise_dir("hello"), &["solution"]);
+        assert!(matches!(result, Err(Error::InvalidForfeit)));
+    }
+
+    #[test]
+    fn submit_sends_the_test_report() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let exercise_dir = fixture.exercise_dir("hello");
+        // The server sends no grading, so there is nothing to wait for.
+        fixture
+            .run_in(&exercise_dir, &["submit", "--wait"])
+            .unwrap();
+
+        let submitted = fixture.api.submitted.lock().unwrap();
+        let (token, report) = &submitted[0];
+        assert_eq!(token, "token-1");
+        assert_eq!(report.test_exit_code, Some(0));
+        assert_eq!(report.test_output, PASSING_TESTS);
+        assert_eq!(report.test_coverage, "1/1");
+        assert_eq!(report.solution_file, exercise_dir.join("src/lib.rs"));
+    }
+
+    #[test]
+    fn submit_waits_for_grading_and_status_shows_it() {
+        let graded = Grading {
+            submission_id: "solution-1".to_owned(),
+            state: GradingState::Graded,
+            verdict: Some("passed".to_owned()),
+            score: Some(10.0),
+            max_score: Some(10.0),
+            comments: Vec::new(),
+        };
+        let api = with_exercise(FakeApi::default(), "hello", "token-1")
+            .with_gradings("token-1", vec![graded]);
+        let api = with_exercise(api, "other", "token-3");
+        let fixture = Fixture::new(api);
+        let exercise_dir = fixture.exercise_dir("hello");
+        let result = fixture.run_in(&exercise_dir, &["status"]);
+        assert!(matches!(result, Err(Error::MissingMeta)));
+        let result = fixture.run(&["status", "token-3"]);
+        assert!(matches!(result, Err(Error::NotSubmitted { token }) if token == "token-3"));
+
+        fixture.run(&["download", "token-1"]).unwrap();
+        fixture
+            .run_in(&exercise_dir, &["submit", "--wait", "--timeout", "1"])
+            .unwrap();
+        fixture.run_in(&exercise_dir, &["status"]).unwrap();
+        fixture
+            .run(&["status", "token-1", "--output", "json"])
+            .unwrap();
+        let result = fixture.run(&["status", "token-2"]);
+        assert!(matches!(result, Err(Error::ExerciseNotFound { .. })));
+    }
+
+    #[test]
+    fn cache_commands_manage_downloaded_archives() {
+        let fixture = Fixture::new(with_exercise(FakeApi::default(), "hello", "token-1"));
+        fixture.run(&["download", "token-1"]).unwrap();
+        let cache = ArchiveCache::in_dir(fixture.cache.path().to_path_buf(), 64);
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        fixture.run(&["cache", "list"]).unwrap();
+        fixture.run(&["cache", "prune"]).unwrap();
+        assert_eq!(cache.entries().unwrap().len(), 1);
+        fixture.run(&["cache", "prune", "--max-size", "0"]).unwrap();
+        assert!(cache.entries().unwrap().is_empty());
+        fixture.run(&["cache", "clear"]).unwrap();
+    }
+}
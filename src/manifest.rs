@@ -0,0 +1,172 @@
+use crate::checksum::{self, Algorithm};
+use crate::error::*;
+use crate::language::Language;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Checksums of the test files shipped with an exercise, keyed by their path
+/// relative to the exercise directory.
+pub type Manifest = BTreeMap<String, String>;
+
+#[derive(Default)]
+pub struct ManifestDiff {
+    pub modified: Vec<String>,
+    pub missing: Vec<String>,
+    pub extra: Vec<String>,
+}
+
+impl ManifestDiff {
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Modified", &self.modified),
+            ("Missing", &self.missing),
+            ("Extra", &self.extra),
+        ];
+        for (label, files) in sections.iter() {
+            for file in files.iter() {
+                writeln!(f, "  {}: {}", label, file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn collect_files(
+    language: &Language,
+    exercise_dir: &Path,
+    dir: &Path,
+    files: &mut Vec<String>,
+) -> Result<()> {
+    let build_directories = language.build_directories();
+    for entry in fs::read_dir(dir).context(IOError {})? {
+        let path = entry.context(IOError {})?.path();
+        let name = path.file_name().unwrap_or_default();
+        if build_directories.iter().any(|dir| name == *dir) {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_files(language, exercise_dir, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(exercise_dir).unwrap();
+            let components: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            files.push(components.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Lists the test files currently present in the exercise directory.
+fn test_files(language: &Language, exercise_dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    if let Some(test_dir) = language.test_directory() {
+        let test_dir = exercise_dir.join(test_dir);
+        if test_dir.is_dir() {
+            collect_files(language, exercise_dir, &test_dir, &mut files)?;
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Checksums the test files of the exercise. There is no manifest for languages
+/// whose tests sharpener cannot tell apart.
+pub fn build(
+    language: &Language,
+    exercise_dir: &Path,
+    algorithm: Algorithm,
+) -> Result<Option<Manifest>> {
+    if language.test_directory().is_none() {
+        return Ok(None);
+    }
+    test_files(language, exercise_dir)?
+        .into_iter()
+        .map(|file| {
+            let checksum = checksum::checksum_file(&exercise_dir.join(&file), algorithm)?;
+            Ok((file, checksum))
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+pub fn compare(
+    language: &Language,
+    exercise_dir: &Path,
+    manifest: &Manifest,
+) -> Result<ManifestDiff> {
+    let mut diff = ManifestDiff::default();
+    for (file, expected) in manifest.iter() {
+        let path = exercise_dir.join(file);
+        if !path.is_file() {
+            diff.missing.push(file.clone());
+        } else if !checksum::file_matches(&path, expected)? {
+            diff.modified.push(file.clone());
+        }
+    }
+
+    diff.extra = test_files(language, exercise_dir)?
+        .into_iter()
+        .filter(|file| !manifest.contains_key(file))
+        .collect();
+    Ok(diff)
+}
+
+/// Combines every entry of the manifest into a single hex digest, without prefix.
+/// The digest covers one `<path> <algorithm>:<hex digest>\n` line per file, sorted
+/// by path, all hashed with `algorithm`.
+pub fn digest(manifest: &Manifest, algorithm: Algorithm) -> String {
+    let lines: String = manifest
+        .iter()
+        .map(|(file, checksum)| format!("{} {}\n", file, checksum))
+        .collect();
+    checksum::hex_digest(lines.as_bytes(), algorithm)
+}
+
+/// Prints a warning when the test files no longer match the ones downloaded.
+/// Exercises downloaded before manifests were recorded are not checked.
+pub fn warn_if_modified(
+    language: &Language,
+    exercise_dir: &Path,
+    manifest: Option<&Manifest>,
+) -> Result<()> {
+    if let Some(manifest) = manifest {
+        let diff = compare(language, exercise_dir, manifest)?;
+        if !diff.is_clean() {
+            eprintln!(
+                "Warning: the test files differ from the ones that were downloaded, results may not match grading:\n{}",
+                diff
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_one_line_per_file_in_path_order() {
+        let algorithm = Algorithm::Sha256;
+        let mut manifest = Manifest::new();
+        manifest.insert("tests/b.rs".to_owned(), "sha256:bb".to_owned());
+        manifest.insert("tests/a.rs".to_owned(), "sha256:aa".to_owned());
+        let lines = "tests/a.rs sha256:aa\ntests/b.rs sha256:bb\n";
+        assert_eq!(
+            digest(&manifest, algorithm),
+            checksum::hex_digest(lines.as_bytes(), algorithm)
+        );
+        assert!(!digest(&manifest, algorithm).contains(':'));
+    }
+}
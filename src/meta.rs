@@ -1,7 +1,7 @@
 use crate::error::*;
 use crate::language::Language;
+use crate::manifest::Manifest;
 use serde::{Deserialize, Serialize};
-use std::env;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 
@@ -22,12 +22,26 @@ pub struct Meta {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub submission_token: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_args: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_checksums: Option<Manifest>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_checksum: Option<String>,
+
+    /// Directory the exercise archive unpacks into, which is named after the
+    /// exercise on the server rather than `name`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_root: Option<String>,
 }
 
 impl Meta {
-    pub fn get() -> Result<(Self, PathBuf)> {
-        let working_dir = env::current_dir().context(IOError {})?;
-        let mut current_dir = Some(working_dir.as_path());
+    /// Finds the exercise containing `working_dir`, along with its meta file path.
+    pub fn find(working_dir: &Path) -> Result<(Self, PathBuf)> {
+        let mut current_dir = Some(working_dir);
         while let Some(dir) = current_dir {
             let path = dir.join(".meta.json");
             let result = File::open(&path)
@@ -43,6 +57,11 @@ impl Meta {
         Err(Error::MissingMeta)
     }
 
+    pub fn load(path: &Path) -> Result<Self> {
+        let meta_file = File::open(path).context(OpenMetaFile {})?;
+        serde_json::from_reader(&meta_file).context(ParseMetaFile {})
+    }
+
     pub fn write(&self, path: &Path) -> Result<()> {
         let meta_file = File::create(path).context(OpenMetaFile {})?;
         serde_json::to_writer_pretty(&meta_file, self).context(WriteMetaFile {})?;
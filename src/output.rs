@@ -0,0 +1,147 @@
+use crate::error::*;
+use serde::Serialize;
+use serde_json::json;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Version of the documents printed with `--output json`. It changes whenever a
+/// field is removed or changes meaning, while new fields may appear at any time.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How commands print their results.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// One JSON document per line, on stdout.
+    Json,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 2] = ["text", "json"];
+
+    pub fn is_text(self) -> bool {
+        self == Self::Text
+    }
+
+    /// Prints the result of `command` to `out`: `text` for people, or `data` for
+    /// programs.
+    pub fn print<T, F>(self, out: &mut dyn Write, command: &str, data: &T, text: F) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+        F: FnOnce() -> String,
+    {
+        match self {
+            Self::Text => writeln!(out, "{}", text()).context(IOError {}),
+            Self::Json => self.print_data(out, command, data),
+        }
+    }
+
+    /// Prints `data` for programs, for commands that already showed people what
+    /// they did while doing it.
+    pub fn print_data<T>(self, out: &mut dyn Write, command: &str, data: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        if self == Self::Json {
+            let document = document(command, "data", json!(data));
+            writeln!(out, "{}", document).context(IOError {})?;
+        }
+        Ok(())
+    }
+
+    pub fn print_error(self, command: &str, error: &Error) {
+        match self {
+            Self::Text => eprintln!("Error: {}", error),
+            Self::Json => println!("{}", error_document(command, error)),
+        }
+    }
+
+    /// Prints why the arguments were refused. The command is unknown then, so it
+    /// is null in JSON output.
+    pub fn print_usage_error(self, message: &str) {
+        match self {
+            Self::Text => eprintln!("{}", message),
+            Self::Json => println!("{}", usage_error_document(message)),
+        }
+    }
+
+    /// Whether `args` ask for JSON output, for when they cannot be parsed.
+    pub fn requested_in<S: AsRef<str>>(args: &[S]) -> Self {
+        let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
+        let json = args.contains(&"--output=json")
+            || args.windows(2).any(|pair| pair == ["--output", "json"]);
+        if json {
+            Self::Json
+        } else {
+            Self::Text
+        }
+    }
+}
+
+fn document(command: &str, key: &str, value: serde_json::Value) -> serde_json::Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "command": command,
+        key: value,
+    })
+}
+
+fn error_document(command: &str, error: &Error) -> serde_json::Value {
+    let error = json!({
+        "kind": error.kind(),
+        "message": error.to_string(),
+    });
+    document(command, "error", error)
+}
+
+fn usage_error_document(message: &str) -> serde_json::Value {
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "command": null,
+        "error": { "kind": "usage", "message": message },
+    })
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown output format \"{}\"", value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_data_in_a_versioned_document() {
+        let document = document("hint", "data", json!({"hints": ["Read the tests"]}));
+        assert_eq!(
+            document.to_string(),
+            r#"{"command":"hint","data":{"hints":["Read the tests"]},"schema_version":1}"#
+        );
+    }
+
+    #[test]
+    fn describes_errors_by_kind() {
+        let document = error_document("hint", &Error::MissingMeta);
+        assert_eq!(document["error"]["kind"], "missing_meta");
+        assert!(document["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Exercise metadata not found"));
+    }
+
+    #[test]
+    fn finds_json_output_in_unparsed_arguments() {
+        let requested = |args: &[&str]| OutputFormat::requested_in(args);
+        assert_eq!(requested(&["list", "--output", "json"]), OutputFormat::Json);
+        assert_eq!(requested(&["--output=json", "lst"]), OutputFormat::Json);
+        assert_eq!(requested(&["list", "json"]), OutputFormat::Text);
+    }
+}
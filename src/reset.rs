@@ -0,0 +1,154 @@
+use crate::api::ApiClient;
+use crate::archive::{self, Archive};
+use crate::cache::ArchiveCache;
+use crate::checksum;
+use crate::error::*;
+use crate::manifest;
+use crate::meta::Meta;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ResetScope {
+    Tests,
+    All,
+}
+
+fn original_archive(api: &dyn ApiClient, cache: &ArchiveCache, meta: &Meta) -> Result<Archive> {
+    let cached = meta
+        .archive_checksum
+        .as_ref()
+        .and_then(|checksum| cache.get(checksum));
+    if let Some(archive) = cached {
+        return Ok(archive);
+    }
+
+    eprintln!("Downloading the original exercise again");
+    let token = meta.submission_token.as_ref().context(MissingMeta {})?;
+    let submission = api.get(token)?;
+    submission.fetch_archive(api, cache, true)
+}
+
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn backup_dir(exercise_dir: &Path) -> PathBuf {
+    exercise_dir
+        .join(".sharpener/backups")
+        .join(timestamp().to_string())
+}
+
+/// Copies every file from `source` into `destination`, recreating directories the
+/// learner removed. Existing files that would change are first moved into `backup`
+/// when one is given.
+fn restore_files(
+    source: &Path,
+    destination: &Path,
+    backup: Option<&Path>,
+    backed_up: &mut usize,
+) -> Result<()> {
+    fs::create_dir_all(destination).context(IOError {})?;
+    for entry in fs::read_dir(source).context(IOError {})? {
+        let entry = entry.context(IOError {})?;
+        let source_path = entry.path();
+        let destination_path = destination.join(entry.file_name());
+
+        if source_path.is_dir() {
+            let backup = backup.map(|dir| dir.join(entry.file_name()));
+            restore_files(
+                &source_path,
+                &destination_path,
+                backup.as_deref(),
+                backed_up,
+            )?;
+            continue;
+        }
+
+        let original = fs::read(&source_path).context(IOError {})?;
+        match fs::read(&destination_path) {
+            Ok(current) if current == original => continue,
+            Ok(_) => {
+                if let Some(backup) = backup {
+                    fs::create_dir_all(backup).context(IOError {})?;
+                    fs::copy(&destination_path, backup.join(entry.file_name()))
+                        .context(IOError {})?;
+                    *backed_up += 1;
+                }
+            }
+            Err(_) => (),
+        }
+        fs::write(&destination_path, original).context(IOError {})?;
+    }
+    Ok(())
+}
+
+/// What a reset changed.
+pub struct Restored {
+    /// How many changed files were saved before being restored.
+    pub backed_up: usize,
+    pub backup_dir: PathBuf,
+}
+
+/// Restores the files covered by `scope` from `archive` into the exercise that
+/// `meta_path` belongs to, keeping the learner's progress in the metadata.
+pub fn restore(
+    archive: &Archive,
+    meta: &mut Meta,
+    meta_path: &Path,
+    scope: ResetScope,
+) -> Result<Restored> {
+    let exercise_dir = meta_path.parent().unwrap();
+    // Exercises downloaded before the root was recorded unpack into their name.
+    let root = meta.archive_root.as_ref().unwrap_or(&meta.name);
+    let unpacked = tempfile::tempdir().context(IOError {})?;
+    archive::unpack(archive, unpacked.path(), root)?;
+    let original_dir = unpacked.path().join(root);
+    // The exercise metadata tracks the learner's progress, so it is never restored.
+    fs::remove_file(original_dir.join(".meta.json")).context(IOError {})?;
+
+    let mut backed_up = 0;
+    let backup = backup_dir(exercise_dir);
+    match scope {
+        ResetScope::Tests => {
+            if let Some(test_directory) = meta.language.test_directory() {
+                restore_files(
+                    &original_dir.join(test_directory),
+                    &exercise_dir.join(test_directory),
+                    None,
+                    &mut backed_up,
+                )?;
+            }
+        }
+        ResetScope::All => {
+            restore_files(&original_dir, exercise_dir, Some(&backup), &mut backed_up)?;
+        }
+    }
+
+    meta.archive_checksum = Some(checksum::checksum_bytes(
+        &archive.data,
+        checksum::DEFAULT_ALGORITHM,
+    ));
+    meta.test_checksums =
+        manifest::build(&meta.language, &original_dir, checksum::DEFAULT_ALGORITHM)?;
+    meta.write(meta_path)?;
+    Ok(Restored {
+        backed_up,
+        backup_dir: backup,
+    })
+}
+
+pub fn reset(
+    api: &dyn ApiClient,
+    cache: &ArchiveCache,
+    working_dir: &Path,
+    scope: ResetScope,
+) -> Result<Restored> {
+    let (mut meta, meta_path) = Meta::find(working_dir)?;
+    let archive = original_archive(api, cache, &meta)?;
+    restore(&archive, &mut meta, &meta_path, scope)
+}
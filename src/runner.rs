@@ -0,0 +1,182 @@
+use crate::error::*;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+pub struct TestRun {
+    pub stdout: String,
+    pub stderr: String,
+    /// Both streams in the order their lines were produced.
+    pub combined: String,
+    pub exit_code: Option<i32>,
+}
+
+impl TestRun {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+fn forward_lines<R>(reader: R, stream: Stream, sender: Sender<(Stream, Vec<u8>)>)
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let mut line = Vec::new();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send((stream, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Runs the test commands of exercises, so that tests can stand in for them.
+pub trait TestRunner: Send + Sync {
+    /// Runs `command` with the terminal attached and returns its exit code.
+    fn run_attached(&self, command: Command) -> Result<Option<i32>>;
+
+    /// Runs `command` capturing its output, see `run_tests`.
+    fn run_captured(&self, command: Command, echo: bool) -> Result<TestRun>;
+}
+
+/// Runs test commands as child processes.
+pub struct ProcessRunner;
+
+impl TestRunner for ProcessRunner {
+    fn run_attached(&self, mut command: Command) -> Result<Option<i32>> {
+        let status = command
+            .spawn()
+            .and_then(|mut child| child.wait())
+            .context(TestCommand {})?;
+        Ok(status.code())
+    }
+
+    fn run_captured(&self, command: Command, echo: bool) -> Result<TestRun> {
+        run_tests(command, echo)
+    }
+}
+
+/// Runs the test command capturing stdout, stderr and the exit code. When `echo`
+/// is set the output is also shown as it is produced.
+pub fn run_tests(mut command: Command, echo: bool) -> Result<TestRun> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(TestCommand {})?;
+
+    let (sender, receiver) = channel();
+    forward_lines(child.stdout.take().unwrap(), Stream::Stdout, sender.clone());
+    forward_lines(child.stderr.take().unwrap(), Stream::Stderr, sender);
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut combined = Vec::new();
+    for (stream, line) in receiver {
+        if echo {
+            // Echoing is best effort, a closed terminal must not abort the run.
+            let _ = match stream {
+                Stream::Stdout => io::stdout().write_all(&line),
+                Stream::Stderr => io::stderr().write_all(&line),
+            };
+        }
+        combined.extend_from_slice(&line);
+        match stream {
+            Stream::Stdout => stdout.extend(line),
+            Stream::Stderr => stderr.extend(line),
+        }
+    }
+
+    let status = child.wait().context(TestCommand {})?;
+    Ok(TestRun {
+        stdout: String::from_utf8(stdout).context(InvalidTestOutput {})?,
+        stderr: String::from_utf8(stderr).context(InvalidTestOutput {})?,
+        combined: String::from_utf8(combined).context(InvalidTestOutput {})?,
+        exit_code: status.code(),
+    })
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers every test command with the same run, recording the commands.
+    pub struct FakeRunner {
+        stdout: String,
+        exit_code: Option<i32>,
+        pub commands: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl FakeRunner {
+        pub fn new(stdout: &str, exit_code: Option<i32>) -> Self {
+            Self {
+                stdout: stdout.to_owned(),
+                exit_code,
+                commands: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn record(&self, command: &Command) {
+            let words = Some(command.get_program())
+                .into_iter()
+                .chain(command.get_args())
+                .map(|word| word.to_string_lossy().into_owned())
+                .collect();
+            self.commands.lock().unwrap().push(words);
+        }
+    }
+
+    impl TestRunner for FakeRunner {
+        fn run_attached(&self, command: Command) -> Result<Option<i32>> {
+            self.record(&command);
+            Ok(self.exit_code)
+        }
+
+        fn run_captured(&self, command: Command, _echo: bool) -> Result<TestRun> {
+            self.record(&command);
+            Ok(TestRun {
+                stdout: self.stdout.clone(),
+                stderr: String::new(),
+                combined: self.stdout.clone(),
+                exit_code: self.exit_code,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn captures_both_streams_and_the_exit_code() {
+        let mut command = Command::new("sh");
+        // The pauses keep the order of lines across streams predictable.
+        command.args([
+            "-c",
+            "echo one; sleep 0.2; echo two >&2; sleep 0.2; echo three; exit 3",
+        ]);
+        let test_run = run_tests(command, false).unwrap();
+        assert_eq!(test_run.stdout, "one\nthree\n");
+        assert_eq!(test_run.stderr, "two\n");
+        assert_eq!(test_run.combined, "one\ntwo\nthree\n");
+        assert_eq!(test_run.exit_code, Some(3));
+        assert!(!test_run.success());
+    }
+}
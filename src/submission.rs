@@ -1,41 +1,131 @@
+use crate::api::{ApiClient, SubmissionReport};
+use crate::archive::{self, Archive};
+use crate::cache::ArchiveCache;
+use crate::checksum;
 use crate::error::*;
+use crate::gate;
+use crate::grading::Grading;
 use crate::language::Language;
+use crate::manifest;
 use crate::meta::Meta;
-use flate2::read::GzDecoder;
-use reqwest::{multipart::Form, Client, StatusCode, Url};
-use serde::{de::Error as DeserializeError, Deserialize, Deserializer};
-use std::fs::{File, OpenOptions};
-use std::io::{Seek, SeekFrom};
-use std::path::Path;
-use tar;
+use crate::reset::{self, ResetScope};
+use crate::runner::TestRunner;
+use serde::{de::Error as DeserializeError, Deserialize, Deserializer, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SubmissionStatus {
     Skipped,
     Submitted,
     Pending,
 }
 
+impl SubmissionStatus {
+    pub const NAMES: [&'static str; 3] = ["skipped", "submitted", "pending"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Skipped => "skipped",
+            Self::Submitted => "submitted",
+            Self::Pending => "pending",
+        }
+    }
+}
+
+impl FromStr for SubmissionStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use SubmissionStatus::*;
+        match value {
+            "skipped" => Ok(Skipped),
+            "submitted" => Ok(Submitted),
+            "pending" => Ok(Pending),
+            _ => Err(format!("unknown submission status \"{}\"", value)),
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for SubmissionStatus {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        use SubmissionStatus::*;
         let value = String::deserialize(deserializer)?;
-        match value.as_str() {
-            "skipped" => Ok(Skipped),
-            "submitted" => Ok(Submitted),
-            "pending" => Ok(Pending),
-            _ => Err(DeserializeError::unknown_variant(
-                &value,
-                &["skipped", "submitted", "pending"],
-            )),
+        value
+            .parse()
+            .map_err(|_| DeserializeError::unknown_variant(&value, &Self::NAMES))
+    }
+}
+
+/// Narrows down which submissions are listed. Sent to the server as query
+/// parameters, and checked again on what it returns.
+#[derive(Clone, Default)]
+pub struct SubmissionQuery {
+    pub status: Option<SubmissionStatus>,
+    pub language: Option<String>,
+    pub topic: Option<String>,
+}
+
+impl SubmissionQuery {
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(status) = self.status {
+            params.push(("status", status.as_str().to_owned()));
+        }
+        if let Some(language) = self.language.as_ref() {
+            params.push(("language", language.clone()));
         }
+        if let Some(topic) = self.topic.as_ref() {
+            params.push(("topic", topic.clone()));
+        }
+        params
+    }
+
+    pub fn matches(&self, submission: &Submission) -> bool {
+        let status_matches = self
+            .status
+            .iter()
+            .all(|status| submission.submission_status == *status);
+        let language_matches = self
+            .language
+            .iter()
+            .all(|language| submission.exercise_language.as_str() == language);
+        let topic_matches = self
+            .topic
+            .iter()
+            .all(|topic| submission.exercise_topics.contains(topic));
+        status_matches && language_matches && topic_matches
     }
 }
 
-#[derive(Deserialize)]
+/// What `download` does when the exercise directory already exists.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ExistingExercise {
+    Refuse,
+    /// Replaces copies of the same submission only.
+    Overwrite,
+    /// Replaces copies of any submission.
+    OverwriteForeign,
+    Backup,
+    UpdateTests,
+}
+
+/// A solution that was sent to the server.
+#[derive(Serialize)]
+pub struct Submitted {
+    pub submission_token: String,
+    /// How the solution fared against the tests, see `gate::Verdict`.
+    pub verdict: &'static str,
+    pub tests_passed: String,
+    pub test_exit_code: Option<i32>,
+    /// Missing when the server does not grade solutions.
+    pub grading: Option<Grading>,
+}
+
+#[derive(Clone, Deserialize)]
 pub struct Submission {
     pub exercise_name: String,
     pub exercise_language: Language,
@@ -43,176 +133,238 @@ pub struct Submission {
     pub submission_token: String,
     pub attempts: i32,
     pub submission_status: SubmissionStatus,
-}
 
-#[derive(Deserialize)]
-struct ForfeitSubmission {
-    pub success: bool,
-    pub data: Option<Submission>,
+    #[serde(default)]
+    pub exercise_topics: Vec<String>,
+
+    /// Checksum of the exercise archive, as `<algorithm>:<hex digest>`.
+    #[serde(default)]
+    pub archive_checksum: Option<String>,
+
+    /// Size of the exercise archive in bytes.
+    #[serde(default)]
+    pub archive_size: Option<u64>,
 }
 
 impl Submission {
-    pub fn list(client: Client, api: &Url, status: Option<SubmissionStatus>) -> Result<Vec<Self>> {
-        let url = api.join("submissions").unwrap();
-        let result = client
-            .get(url)
-            .send()
-            .context(ServerRequest {})
-            .and_then(|response| {
-                ensure!(
-                    response.status() == StatusCode::OK,
-                    InvalidAPIResponse {
-                        expected: StatusCode::OK,
-                        received: response.status()
-                    }
-                );
-                serde_json::from_reader(response).context(ParseSubmissionResponse {})
-            });
-
-        match status {
-            Some(s) => result.map(|submissions: Vec<Self>| {
-                submissions
-                    .into_iter()
-                    .filter(|sub| sub.submission_status == s)
-                    .collect()
-            }),
-            None => result,
-        }
+    /// Lists the submissions matching `query`, even from servers that ignore it.
+    pub fn list(api: &dyn ApiClient, query: &SubmissionQuery) -> Result<Vec<Self>> {
+        let submissions = api.list(query)?;
+        Ok(submissions
+            .into_iter()
+            .filter(|submission| query.matches(submission))
+            .collect())
     }
 
-    pub fn get(client: Client, api: &Url, token: &str) -> Result<Self> {
-        let suffix = format!("submissions/{}", token);
-        let url = api.join(&suffix).unwrap();
-        client
-            .get(url)
-            .send()
-            .context(ServerRequest {})
-            .and_then(|response| {
-                ensure!(
-                    response.status() == StatusCode::OK,
-                    InvalidAPIResponse {
-                        expected: StatusCode::OK,
-                        received: response.status()
-                    }
-                );
-                serde_json::from_reader(response).context(ParseSubmissionResponse {})
-            })
-    }
-
-    pub fn download(&self) -> Result<()> {
-        let download = reqwest::get(&self.download_url).context(ExerciseDownload {})?;
-        let decoded = GzDecoder::new(download);
-        let mut archive = tar::Archive::new(decoded);
-        archive.unpack("./").context(UnpackTar {})?;
+    /// Gets the exercise archive from the cache or downloads it, checking it against
+    /// what the server announced. Cached copies are also used when the download fails.
+    pub fn fetch_archive(
+        &self,
+        api: &dyn ApiClient,
+        cache: &ArchiveCache,
+        progress: bool,
+    ) -> Result<Archive> {
+        let expected_checksum = self.archive_checksum.as_deref();
+        if let Some(archive) = expected_checksum.and_then(|checksum| cache.get(checksum)) {
+            return Ok(archive);
+        }
 
-        let directory_path = Path::new(&self.exercise_name);
-        let meta_path = directory_path.join(".meta.json");
-        let mut meta_file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(meta_path)
-            .context(OpenMetaFile {})?;
+        let archive = match api.download(&self.download_url, progress) {
+            Ok(fetched) => Archive::new(fetched.data, fetched.content_type.as_deref())?,
+            Err(error) => match cache.get_by_url(&self.download_url) {
+                Some(archive) => {
+                    eprintln!("Warning: {}. Using a cached copy of the exercise.", error);
+                    archive
+                }
+                None => return Err(error),
+            },
+        };
+        archive::verify(&archive.data, expected_checksum, self.archive_size)?;
+        // The cache only saves downloads, so failing to fill it is not fatal.
+        if let Err(error) = cache.store(&archive, &self.exercise_name, &self.download_url) {
+            eprintln!("Warning: Unable to cache the exercise archive: {}", error);
+        }
+        Ok(archive)
+    }
 
-        let mut meta: Meta = serde_json::from_reader(&meta_file).context(ParseMetaFile {})?;
-        meta.submission_token = Some(self.submission_token.clone());
-        meta.hints_seen = Some(0);
-        meta_file
-            .seek(SeekFrom::Start(0))
-            .context(OpenMetaFile {})?;
-        serde_json::to_writer_pretty(&meta_file, &meta).context(WriteMetaFile {})?;
-        Ok(())
+    /// Refuses exercise names that would lead `download` out of its destination.
+    fn check_name(&self) -> Result<()> {
+        let mut components = Path::new(&self.exercise_name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(()),
+            _ => UnsafeArchive {
+                path: PathBuf::from(&self.exercise_name),
+                reason: "the exercise name must be a single directory name",
+            }
+            .fail(),
+        }
     }
 
-    pub fn forfeit(client: Client, api: &Url, meta: &Meta) -> Result<Self> {
-        let suffix = format!(
-            "submissions/{}/forfeit",
-            &meta.submission_token.as_ref().context(MissingMeta {})?
-        );
-        let url = api.join(&suffix).unwrap();
-        let response = client.post(url).send().context(ServerRequest {})?;
-        ensure!(
-            response.status() == StatusCode::OK,
-            InvalidAPIResponse {
-                expected: StatusCode::OK,
-                received: response.status()
+    /// Checks that `existing` allows touching the copy of the exercise already in
+    /// `directory_path`, before anything gets downloaded.
+    fn check_existing(&self, directory_path: &Path, existing: ExistingExercise) -> Result<()> {
+        let token = Meta::load(&directory_path.join(".meta.json"))
+            .ok()
+            .and_then(|meta| meta.submission_token);
+        let same_submission = token.as_ref() == Some(&self.submission_token);
+        let path = directory_path.to_path_buf();
+
+        match existing {
+            ExistingExercise::Refuse => ExerciseExists { path }.fail(),
+            ExistingExercise::Overwrite if token.is_some() && !same_submission => ForeignExercise {
+                path,
+                action: "replace it without --replace-other-submission",
             }
-        );
-        let forfeit: ForfeitSubmission =
-            serde_json::from_reader(response).context(ParseSubmissionResponse {})?;
-        match (forfeit.success, forfeit.data) {
-            (true, Some(submission)) => Ok(submission),
-            _ => Err(Error::InvalidForfeit),
+            .fail(),
+            ExistingExercise::UpdateTests if !same_submission => ForeignExercise {
+                path,
+                action: "update its tests",
+            }
+            .fail(),
+            _ => Ok(()),
         }
     }
 
-    pub fn submit(client: Client, api: &Url) -> Result<()> {
-        let (meta, path) = Meta::get()?;
-        let token = meta.submission_token.context(MissingMeta)?;
+    /// Unpacks the exercise next to `directory_path` and only then swaps it in, so
+    /// that the existing copy survives archives that fail to unpack.
+    fn replace_directory(&self, directory_path: &Path, archive: &Archive) -> Result<()> {
+        let parent = directory_path.parent().unwrap();
+        let unpacked = tempfile::Builder::new()
+            .prefix(".sharpener-")
+            .tempdir_in(parent)
+            .context(IOError {})?;
+        archive::unpack(archive, unpacked.path(), &self.exercise_name)?;
 
-        println!("Running tests");
-        let output = meta.language.test_command().output().context(TestCommand)?;
+        // The replaced copy is removed along with the temporary directory.
+        let replaced = unpacked.path().join(".replaced");
+        fs::rename(directory_path, &replaced).context(IOError {})?;
+        if let Err(error) = fs::rename(unpacked.path().join(&self.exercise_name), directory_path) {
+            let _ = fs::rename(&replaced, directory_path);
+            return Err(error).context(IOError {});
+        }
+        Ok(())
+    }
 
-        let test_output = String::from_utf8(output.stdout).context(InvalidTestOutput)?;
+    /// Downloads the exercise into `destination` and returns the exercise directory.
+    pub fn download(
+        &self,
+        api: &dyn ApiClient,
+        cache: &ArchiveCache,
+        destination: &Path,
+        existing: ExistingExercise,
+        progress: bool,
+    ) -> Result<PathBuf> {
+        self.check_name()?;
+        let directory = destination.join(&self.exercise_name);
+        let directory_path = directory.as_path();
+        let exists = directory_path.exists();
+        if exists {
+            self.check_existing(directory_path, existing)?;
+        }
 
-        let test_coverage = meta.language.parse_test_coverage(&test_output);
-        let parent = path.parent().unwrap();
-        let test_file_path = parent.join(meta.language.test_file_path());
-        let test_file_checksum = checksum_file(&test_file_path)?;
-
-        let solution_file_path = parent.join(meta.language.solution_file_path());
-
-        let form = Form::new()
-            .text("test_output", test_output)
-            .text("test_coverage", test_coverage)
-            .text("test_checksum", test_file_checksum)
-            .file("solution", &solution_file_path)
-            .context(OpenSubmissionFile {
-                filename: solution_file_path,
-            })?;
-
-        println!("Submitting results");
-        let suffix = format!("submissions/{}", token);
-        let url = api.join(&suffix).unwrap();
-        let response = client
-            .post(url)
-            .multipart(form)
-            .send()
-            .context(ServerRequest)?;
-
-        ensure!(
-            response.status() == StatusCode::OK,
-            InvalidAPIResponse {
-                expected: StatusCode::OK,
-                received: response.status()
+        let archive = self.fetch_archive(api, cache, progress)?;
+        let meta_path = directory_path.join(".meta.json");
+        match existing {
+            _ if !exists => {
+                fs::create_dir_all(destination).context(IOError {})?;
+                archive::unpack(&archive, destination, &self.exercise_name)?;
+            }
+            ExistingExercise::Refuse => unreachable!("refused by check_existing"),
+            ExistingExercise::Overwrite | ExistingExercise::OverwriteForeign => {
+                self.replace_directory(directory_path, &archive)?;
             }
-        );
+            ExistingExercise::Backup => {
+                let backup_name = format!("{}.backup-{}", self.exercise_name, reset::timestamp());
+                let backup = directory_path.with_file_name(backup_name);
+                fs::rename(directory_path, &backup).context(IOError {})?;
+                eprintln!("Moved the existing exercise to {}", backup.display());
+                archive::unpack(&archive, destination, &self.exercise_name)?;
+            }
+            ExistingExercise::UpdateTests => {
+                let mut meta = Meta::load(&meta_path)?;
+                reset::restore(&archive, &mut meta, &meta_path, ResetScope::Tests)?;
+                return Ok(directory);
+            }
+        }
 
-        Ok(())
+        let mut meta = Meta::load(&meta_path)?;
+        meta.submission_token = Some(self.submission_token.clone());
+        meta.hints_seen = Some(0);
+        meta.archive_root = Some(self.exercise_name.clone());
+        meta.archive_checksum = Some(checksum::checksum_bytes(
+            &archive.data,
+            checksum::DEFAULT_ALGORITHM,
+        ));
+        meta.test_checksums =
+            manifest::build(&meta.language, directory_path, checksum::DEFAULT_ALGORITHM)?;
+        meta.write(&meta_path)?;
+        Ok(directory)
     }
-}
 
-fn checksum_file(path: &Path) -> Result<String> {
-    use md5::Context;
-    use std::io::{BufRead, BufReader};
-
-    let file = File::open(path).with_context(|| OpenSubmissionFile {
-        filename: path.to_path_buf(),
-    })?;
-    let mut buffed_file = BufReader::new(file);
+    /// Runs the graded tests and sends their results. Progress is only printed
+    /// when `echo` is set, and failing solutions are then confirmed interactively.
+    pub fn submit(
+        api: &dyn ApiClient,
+        runner: &dyn TestRunner,
+        working_dir: &Path,
+        force: bool,
+        assume_yes: bool,
+        echo: bool,
+    ) -> Result<Submitted> {
+        let (meta, path) = Meta::find(working_dir)?;
+        let token = meta.submission_token.context(MissingMeta)?;
 
-    let mut context = Context::new();
+        let parent = path.parent().unwrap();
+        manifest::warn_if_modified(&meta.language, parent, meta.test_checksums.as_ref())?;
 
-    loop {
-        let buffer = buffed_file.fill_buf().with_context(|| ReadSubmissionFile {
-            filename: path.to_path_buf(),
-        })?;
+        if echo {
+            println!("Running tests");
+        }
+        // Graded runs ignore filters and saved runner arguments on purpose.
+        let mut test_command = meta.language.test_command();
+        test_command.current_dir(parent);
+        let test_run = runner.run_captured(test_command, echo)?;
 
-        if buffer.is_empty() {
-            return Ok(format!("{:x}", context.compute()));
+        let test_results = meta.language.parse_test_results(&test_run.stdout);
+        let test_coverage = meta.language.parse_test_coverage(&test_run.stdout);
+        if echo {
+            println!("\nTests passed: {}", test_coverage);
         }
+        let verdict = gate::evaluate(test_results, test_run.exit_code);
+        let confirm: fn(&str) -> Result<bool> = if echo {
+            gate::confirm_on_terminal
+        } else {
+            gate::refuse_without_prompt
+        };
+        gate::allow_submission(&verdict, force, assume_yes, confirm)?;
 
-        context.consume(&buffer);
-        let consumed = buffer.len();
-        buffed_file.consume(consumed);
+        let algorithm = checksum::DEFAULT_ALGORITHM;
+        let test_checksums =
+            manifest::build(&meta.language, parent, algorithm)?.unwrap_or_default();
+        let report = SubmissionReport {
+            test_output: test_run.stdout,
+            test_errors: test_run.stderr,
+            test_log: test_run.combined,
+            test_exit_code: test_run.exit_code,
+            test_coverage,
+            test_checksum: manifest::digest(&test_checksums, algorithm),
+            test_checksum_algorithm: algorithm,
+            test_checksums,
+            solution_file: parent.join(meta.language.solution_file_path()),
+        };
+
+        if echo {
+            println!("Submitting results");
+        }
+        let test_exit_code = report.test_exit_code;
+        let tests_passed = report.test_coverage.clone();
+        let grading = api.submit(&token, report)?;
+        Ok(Submitted {
+            submission_token: token,
+            verdict: verdict.as_str(),
+            tests_passed,
+            test_exit_code,
+            grading,
+        })
     }
 }
@@ -0,0 +1,30 @@
+/// Lays out rows as columns aligned under `header`. The last column is not padded.
+pub fn render_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let header: Vec<String> = header.iter().map(|title| (*title).to_owned()).collect();
+    let table: Vec<&Vec<String>> = Some(&header).into_iter().chain(rows).collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| {
+            table
+                .iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let mut output = String::new();
+    for row in table {
+        let last = row.len() - 1;
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| match column {
+                column if column == last => cell.clone(),
+                column => format!("{:width$}", cell, width = widths[column]),
+            })
+            .collect();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
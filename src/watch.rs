@@ -0,0 +1,101 @@
+use crate::error::*;
+use crate::meta::Meta;
+use crate::runner;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+static CLEAR_SCREEN: &str = "\x1B[2J\x1B[1;1H";
+
+fn is_ignored(meta: &Meta, exercise_dir: &Path, path: &Path) -> bool {
+    let build_directories = meta.language.build_directories();
+    let generated_files = meta.language.generated_files();
+    let relative = path.strip_prefix(exercise_dir).unwrap_or(path);
+    relative.components().any(|component| {
+        let component = component.as_os_str();
+        component == ".git"
+            || component == ".sharpener"
+            || component == ".meta.json"
+            || build_directories.iter().any(|dir| component == *dir)
+            || generated_files.iter().any(|file| component == *file)
+    })
+}
+
+fn changed_path(event: &DebouncedEvent) -> Option<&Path> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path)
+        | DebouncedEvent::Rename(_, path) => Some(path),
+        _ => None,
+    }
+}
+
+fn run_tests(meta: &Meta, test_command: &dyn Fn() -> Command) -> Result<()> {
+    print!("{}", CLEAR_SCREEN);
+    println!("Running tests");
+    let test_run = runner::run_tests(test_command(), true)?;
+
+    println!(
+        "\nTests passed: {}",
+        meta.language.parse_test_coverage(&test_run.stdout)
+    );
+    if !test_run.success() {
+        match test_run.exit_code {
+            Some(code) => println!("Test runner exited with code {}", code),
+            None => println!("Test runner was terminated by a signal"),
+        }
+    }
+    println!("Watching for changes. Press Ctrl-C to stop.");
+    Ok(())
+}
+
+pub fn watch_tests(
+    meta: &Meta,
+    meta_path: &Path,
+    test_command: &dyn Fn() -> Command,
+) -> Result<()> {
+    let exercise_dir = meta_path.parent().unwrap();
+    let (sender, receiver) = channel();
+    let mut watcher = watcher(sender, Duration::from_millis(500)).context(WatchFiles {})?;
+    watcher
+        .watch(exercise_dir, RecursiveMode::Recursive)
+        .context(WatchFiles {})?;
+
+    run_tests(meta, test_command)?;
+    for event in receiver.iter() {
+        if let DebouncedEvent::Error(error, _) = event {
+            return Err(error).context(WatchFiles {});
+        }
+
+        let relevant = changed_path(&event)
+            .map(|path| !is_ignored(meta, exercise_dir, path))
+            .unwrap_or(false);
+        if relevant {
+            // Several files saved together arrive as separate events.
+            receiver.try_iter().for_each(drop);
+            run_tests(meta, test_command)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_what_the_test_runner_writes() {
+        let meta: Meta =
+            serde_json::from_str(r#"{"name": "hello", "language": "rust", "difficulty": 1}"#)
+                .unwrap();
+        let exercise_dir = Path::new("/exercises/rust/hello");
+        let ignored = |path: &str| is_ignored(&meta, exercise_dir, &exercise_dir.join(path));
+        assert!(ignored("Cargo.lock"));
+        assert!(ignored("target/debug/hello"));
+        assert!(!ignored("src/lib.rs"));
+        assert!(!ignored("tests/tests.rs"));
+    }
+}
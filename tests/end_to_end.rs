@@ -0,0 +1,342 @@
+//! Runs the CLI binary against `sharpener-mock-server`, serving the exercises in
+//! `tests/fixtures/exercises`. The mock server is an example, so that it is built
+//! by `cargo test` without being installed along with the CLI.
+
+use std::env;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use tempfile::TempDir;
+
+struct MockServer {
+    process: Child,
+    api_url: String,
+}
+
+/// Examples are built into `target/<profile>/examples`, next to the `deps`
+/// directory of the test binaries.
+fn mock_server_path() -> PathBuf {
+    let test_binary = env::current_exe().unwrap();
+    let profile_dir = test_binary.parent().unwrap().parent().unwrap();
+    let name = format!("sharpener-mock-server{}", env::consts::EXE_SUFFIX);
+    let path = profile_dir.join("examples").join(name);
+    assert!(
+        path.is_file(),
+        "{} is missing, build it with `cargo build --example sharpener-mock-server`",
+        path.display()
+    );
+    path
+}
+
+impl MockServer {
+    fn start() -> Self {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/exercises");
+        let mut process = Command::new(mock_server_path())
+            .arg(fixtures)
+            // Small pages, so listing always goes through pagination.
+            .args(["--port", "0", "--page-size", "1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut line = String::new();
+        let stdout = process.stdout.take().unwrap();
+        BufReader::new(stdout).read_line(&mut line).unwrap();
+        let api_url = line.trim().trim_start_matches("Listening on ").to_owned();
+        Self { process, api_url }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// A user of the CLI, with their own home directory and workspace.
+struct User {
+    server: MockServer,
+    home: TempDir,
+}
+
+impl User {
+    fn new() -> Self {
+        let user = Self {
+            server: MockServer::start(),
+            home: tempfile::tempdir().unwrap(),
+        };
+        let workspace = user.workspace();
+        user.sharpener(
+            user.home.path(),
+            &[
+                "config",
+                "user-token",
+                "--workspace",
+                workspace.to_str().unwrap(),
+            ],
+        );
+        user
+    }
+
+    fn workspace(&self) -> PathBuf {
+        self.home.path().join("exercises")
+    }
+
+    fn exercise_dir(&self, name: &str) -> PathBuf {
+        self.workspace().join("rust").join(name)
+    }
+
+    fn run(&self, dir: &Path, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_sharpener-cli"))
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", self.home.path())
+            .env("XDG_CACHE_HOME", self.home.path().join(".cache"))
+            .env("SHARPENER_API_URL", &self.server.api_url)
+            .output()
+            .unwrap()
+    }
+
+    /// Runs a command that must succeed and returns its output.
+    fn sharpener(&self, dir: &Path, args: &[&str]) -> String {
+        let output = self.run(dir, args);
+        assert!(
+            output.status.success(),
+            "sharpener {:?} failed:\n{}{}",
+            args,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+#[test]
+fn lists_and_downloads_pending_exercises() {
+    let user = User::new();
+    let list = user.sharpener(user.home.path(), &["list", "--format", "csv"]);
+    assert!(list.contains("answer,rust,pending,0,token-1,\n"));
+    assert!(list.contains("reverse,rust,pending,0,token-2,\n"));
+
+    user.sharpener(user.home.path(), &["download", "token-1"]);
+    let exercise_dir = user.exercise_dir("answer");
+    assert!(exercise_dir.join("src/lib.rs").is_file());
+    let list = user.sharpener(user.home.path(), &["list", "--format", "csv"]);
+    let downloaded = format!("answer,rust,pending,0,token-1,{}\n", exercise_dir.display());
+    assert!(list.contains(&downloaded));
+    user.sharpener(&exercise_dir, &["verify"]);
+    let hint = user.sharpener(&exercise_dir, &["hint"]);
+    assert!(hint.contains("The answer is a number"));
+
+    let refused = user.run(user.home.path(), &["download", "token-1"]);
+    assert!(!refused.status.success());
+}
+
+#[test]
+fn explains_server_errors() {
+    let user = User::new();
+    let missing = user.run(user.home.path(), &["download", "token-404"]);
+    assert!(!missing.status.success());
+    let error = String::from_utf8(missing.stderr).unwrap();
+    assert!(error.contains("Submission not found. Check the submission token"));
+}
+
+#[test]
+fn exits_with_a_code_per_error_category() {
+    let user = User::new();
+    let code = |dir: &Path, args: &[&str]| user.run(dir, args).status.code();
+    assert_eq!(code(user.home.path(), &["list", "--sort", "size"]), Some(2));
+    assert_eq!(code(user.home.path(), &["hint"]), Some(4));
+    assert_eq!(code(user.home.path(), &["download", "token-404"]), Some(8));
+
+    user.sharpener(user.home.path(), &["download", "token-2"]);
+    assert_eq!(code(&user.exercise_dir("reverse"), &["test"]), Some(3));
+    assert_eq!(code(&user.exercise_dir("reverse"), &["submit"]), Some(3));
+
+    user.sharpener(user.home.path(), &["config", "bad\ntoken"]);
+    assert_eq!(code(user.home.path(), &["list"]), Some(6));
+}
+
+#[test]
+fn filters_submissions() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "token-1"]);
+    user.sharpener(&user.exercise_dir("answer"), &["submit"]);
+
+    let submitted = user.sharpener(user.home.path(), &["list", "--status", "submitted"]);
+    assert!(submitted.contains("answer"));
+    assert!(!submitted.contains("reverse"));
+
+    let all = user.sharpener(
+        user.home.path(),
+        &["list", "--all", "--language", "rust", "--format", "json"],
+    );
+    let document: serde_json::Value = serde_json::from_str(&all).unwrap();
+    let all = &document["data"];
+    assert_eq!(all[0]["exercise_name"], "answer");
+    assert_eq!(all[0]["submission_status"], "submitted");
+    assert_eq!(all[0]["attempts"], 1);
+    assert_eq!(all[1]["exercise_name"], "reverse");
+    assert_eq!(all[1]["submission_status"], "pending");
+
+    let python = user.sharpener(user.home.path(), &["list", "--all", "--language", "python"]);
+    assert_eq!(python, "No submissions found\n");
+}
+
+#[test]
+fn updates_only_the_given_settings() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["config", "--read-timeout", "45"]);
+    let config = user.sharpener(user.home.path(), &["config", "--show"]);
+    assert!(config.contains("Token: ******oken\n"));
+    let workspace = format!("Workspace: {}\n", user.workspace().display());
+    assert!(config.contains(&workspace));
+    assert!(config.contains("Read timeout: 45 s\n"));
+}
+
+#[test]
+fn saves_the_workspace_as_an_absolute_path() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["config", "--workspace", "elsewhere"]);
+    let src = user.home.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    let config = user.sharpener(&src, &["config", "--show"]);
+    let workspace = format!(
+        "Workspace: {}\n",
+        user.home.path().join("elsewhere").display()
+    );
+    assert!(config.contains(&workspace), "{}", config);
+}
+
+#[test]
+fn prints_json_documents() {
+    let user = User::new();
+    let json = |dir: &Path, args: &[&str]| -> serde_json::Value {
+        let output = user.run(dir, &[args, &["--output", "json"]].concat());
+        let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(document["schema_version"], 1);
+        document
+    };
+
+    let list = json(user.home.path(), &["list"]);
+    assert_eq!(list["command"], "list");
+    assert_eq!(list["data"][0]["submission_token"], "token-1");
+
+    let download = json(user.home.path(), &["download", "token-1"]);
+    let exercise_dir = user.exercise_dir("answer");
+    assert_eq!(
+        download["data"]["exercise_dir"],
+        exercise_dir.to_str().unwrap()
+    );
+
+    let hint = json(&exercise_dir, &["hint"]);
+    assert_eq!(hint["data"]["hints"][0], "The answer is a number");
+    assert_eq!(hint["data"]["total"], 2);
+
+    let config = json(user.home.path(), &["config", "--show"]);
+    assert_eq!(config["data"]["token"], "******oken");
+    let shown = json(user.home.path(), &["config", "show"]);
+    assert_eq!(shown["command"], "config show");
+    assert_eq!(shown["data"]["token"], "******oken");
+    let conflict = json(user.home.path(), &["config", "show", "--cache-limit", "1"]);
+    assert_eq!(conflict["error"]["kind"], "usage");
+
+    let usage = json(user.home.path(), &["lst"]);
+    assert!(usage["command"].is_null());
+    assert_eq!(usage["error"]["kind"], "usage");
+
+    let missing = json(user.home.path(), &["download", "token-404"]);
+    assert_eq!(missing["error"]["kind"], "exercise_not_found");
+
+    let submit = json(&exercise_dir, &["submit"]);
+    assert_eq!(submit["data"]["verdict"], "passing");
+}
+
+#[test]
+fn downloads_all_pending_exercises() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "--all-pending"]);
+    assert!(user.exercise_dir("answer").is_dir());
+    assert!(user.exercise_dir("reverse").is_dir());
+}
+
+#[test]
+fn submits_a_passing_solution() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "token-1"]);
+    user.sharpener(&user.exercise_dir("answer"), &["submit"]);
+
+    let list = user.sharpener(user.home.path(), &["list"]);
+    assert!(!list.contains("answer"));
+    assert!(list.contains("reverse"));
+
+    let again = user.run(&user.exercise_dir("answer"), &["submit"]);
+    let error = String::from_utf8(again.stderr).unwrap();
+    assert!(error.contains("This submission was already graded"));
+}
+
+#[test]
+fn waits_for_grading_feedback() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "token-1"]);
+    let exercise_dir = user.exercise_dir("answer");
+
+    let not_submitted = user.run(&exercise_dir, &["status"]);
+    assert_eq!(not_submitted.status.code(), Some(8));
+    let message = String::from_utf8_lossy(&not_submitted.stderr);
+    assert!(
+        message.contains("No solution was submitted for token-1 yet"),
+        "{}",
+        message
+    );
+
+    let submitted = user.sharpener(&exercise_dir, &["submit", "--wait"]);
+    assert!(submitted.contains("Submission solution-1 is being graded"));
+    assert!(submitted.contains("Verdict: passed\nScore: 10/10"));
+    assert!(submitted.contains("Sharpener: Every test passes, well done!"));
+
+    let status = user.sharpener(user.home.path(), &["status", "token-1", "--output", "json"]);
+    let status: serde_json::Value = serde_json::from_str(&status).unwrap();
+    assert_eq!(status["data"]["grading"]["state"], "graded");
+    assert_eq!(status["data"]["grading"]["score"], 10.0);
+}
+
+#[test]
+fn refuses_to_submit_failing_solution_without_force() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "token-2"]);
+    let exercise_dir = user.exercise_dir("reverse");
+
+    let refused = user.run(&exercise_dir, &["submit"]);
+    assert!(!refused.status.success());
+    user.sharpener(&exercise_dir, &["submit", "--force", "--yes"]);
+}
+
+#[test]
+fn forfeits_an_exercise_for_a_replacement() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "token-2"]);
+    let exercise_dir = user.exercise_dir("reverse");
+
+    let solution = user.sharpener(&exercise_dir, &["solution"]);
+    assert!(solution.contains("sharpener download token-3"));
+    let list = user.sharpener(user.home.path(), &["list", "--format", "csv"]);
+    assert!(list.contains("answer,rust,pending,0,token-3,"));
+    assert!(!list.contains("token-2"));
+}
+
+#[test]
+fn resets_tests_from_the_server() {
+    let user = User::new();
+    user.sharpener(user.home.path(), &["download", "token-1"]);
+    let exercise_dir = user.exercise_dir("answer");
+    let tests = exercise_dir.join("tests/tests.rs");
+    let original = std::fs::read_to_string(&tests).unwrap();
+    std::fs::write(&tests, "").unwrap();
+
+    user.sharpener(user.home.path(), &["cache", "clear"]);
+    user.sharpener(&exercise_dir, &["reset", "--tests"]);
+    assert_eq!(std::fs::read_to_string(&tests).unwrap(), original);
+}
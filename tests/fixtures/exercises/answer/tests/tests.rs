@@ -0,0 +1,4 @@
+#[test]
+fn answers_everything() {
+    assert_eq!(answer::answer(), 42);
+}
@@ -0,0 +1,3 @@
+pub fn reverse(input: &str) -> String {
+    unimplemented!("reverse {}", input)
+}
@@ -0,0 +1,6 @@
+use reverse::reverse;
+
+#[test]
+fn reverses_words() {
+    assert_eq!(reverse("stressed"), "desserts");
+}